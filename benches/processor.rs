@@ -0,0 +1,101 @@
+/*
+    Throughput of parsing and processing, in rows per second. Streams come
+    from `rust_tx::synthetic`, so every run sees the same rows.
+
+        cargo bench
+        cargo bench -- process/dispute_heavy
+        BENCH_ROWS=10000000 cargo bench -- process/
+*/
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rust_tx::synthetic::{write_csv, Profile, Stream};
+use rust_tx::transaction::Transaction;
+use rust_tx::transaction_processor::TransactionProcessor;
+use std::hint::black_box;
+
+const PARSE_ROWS: usize = 100_000;
+
+// rows in each processed stream, BENCH_ROWS to change it
+fn rows() -> usize {
+    std::env::var("BENCH_ROWS").ok().and_then(|rows| rows.parse().ok()).unwrap_or(100_000)
+}
+
+fn profiles() -> [(&'static str, Profile); 4] {
+    [
+        ("deposit_withdrawal", Profile { clients: 1000, withdrawals: 0.4, disputes: 0.0, seed: 1, ..Profile::default() }),
+        ("dispute_heavy", Profile { clients: 1000, withdrawals: 0.2, disputes: 0.3, seed: 2, ..Profile::default() }),
+        ("few_clients", Profile { clients: 10, ..Profile::default() }),
+        ("many_clients", Profile { clients: 1_000_000, ..Profile::default() }),
+    ]
+}
+
+fn parse(c: &mut Criterion) {
+    let rows = PARSE_ROWS;
+    let mut bytes = Vec::new();
+    write_csv(&mut bytes, Stream::new(Profile::default()).take(rows)).unwrap();
+    let records: Vec<csv::StringRecord> = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(bytes.as_slice())
+        .records()
+        .map(|record| record.unwrap())
+        .collect();
+
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Elements(rows as u64));
+    group.bench_function("transaction", |b| {
+        b.iter_batched(
+            || records.clone(),
+            |records| {
+                for record in records {
+                    black_box(Transaction::parse(record).unwrap());
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    // the csv reader as well, reading a string record for every row
+    group.bench_function("csv", |b| {
+        b.iter(|| {
+            let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(bytes.as_slice());
+            for record in reader.records() {
+                black_box(Transaction::parse(record.unwrap()).unwrap());
+            }
+        })
+    });
+    // reading byte records into one record, as main does
+    group.bench_function("bytes", |b| {
+        b.iter(|| {
+            let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(bytes.as_slice());
+            let mut record = csv::ByteRecord::new();
+            while reader.read_byte_record(&mut record).unwrap() {
+                black_box(Transaction::parse_bytes(&record).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn process(c: &mut Criterion) {
+    let rows = rows();
+    let mut group = c.benchmark_group("process");
+    group.throughput(Throughput::Elements(rows as u64));
+    if rows > 1_000_000 {
+        // criterion's smallest sample, large streams take seconds an iteration
+        group.sample_size(10);
+    }
+    for (name, profile) in profiles() {
+        let transactions: Vec<Transaction> = Stream::new(profile).take(rows).collect();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut processor = TransactionProcessor::new();
+                for transaction in transactions.iter() {
+                    let _ = processor.process_transaction(*transaction);
+                }
+                black_box(processor)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, process);
+criterion_main!(benches);
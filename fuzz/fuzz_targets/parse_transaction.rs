@@ -0,0 +1,47 @@
+#![no_main]
+
+use csv::{ByteRecord, StringRecord};
+use libfuzzer_sys::fuzz_target;
+use rust_tx::transaction::Transaction;
+
+// a transaction that parses and is valid can be processed as it is
+fn check(record: StringRecord) {
+    if let Ok(transaction) = Transaction::parse(record) {
+        if transaction.is_valid() {
+            if let Some(amount) = transaction.amount {
+                assert!(amount.is_finite() && amount >= 0.0);
+            }
+        }
+    }
+}
+
+// the byte parser accepts exactly the rows the string parser does
+fn check_bytes(record: ByteRecord) {
+    let parsed = Transaction::parse_bytes(&record);
+    let expected = StringRecord::from_byte_record(record).ok().map(Transaction::parse);
+    match (expected, parsed) {
+        (Some(Ok(expected)), Ok(parsed)) => {
+            assert!(expected.transaction_type == parsed.transaction_type);
+            assert!((expected.client, expected.tx, expected.timestamp) == (parsed.client, parsed.tx, parsed.timestamp));
+            assert!(expected.amount.map(f64::to_bits) == parsed.amount.map(f64::to_bits));
+        }
+        (Some(Ok(_)), Err(err)) => panic!("rejected a row parse accepts: {}", err),
+        (_, Ok(parsed)) => panic!("accepted a row parse rejects: {:?}", parsed),
+        _ => {}
+    }
+}
+
+// arbitrary fields as a single record, and arbitrary bytes read as a csv file
+fuzz_target!(|input: (Vec<String>, Vec<u8>)| {
+    let (fields, bytes) = input;
+    check_bytes(ByteRecord::from(fields.clone()));
+    check(StringRecord::from(fields));
+
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(bytes.as_slice());
+    for record in reader.byte_records().flatten() {
+        check_bytes(record.clone());
+        if let Ok(record) = StringRecord::from_byte_record(record) {
+            check(record);
+        }
+    }
+});
@@ -0,0 +1,121 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use rust_tx::account::Account;
+use rust_tx::clients::CreditLine;
+use rust_tx::transaction::{ClientId, Transaction, TransactionType};
+use rust_tx::transaction_processor::{DisputeTimeout, TimeoutAction, TransactionProcessor};
+use std::collections::{BTreeMap, BTreeSet};
+
+// small id spaces, so rows keep referring to each other
+#[derive(Arbitrary, Debug)]
+struct Row {
+    kind: u8,
+    client: u8,
+    tx: u8,
+    // in cents
+    amount: Option<u16>,
+    // milliseconds since the row before, rows without one carry no timestamp
+    delay: Option<u16>,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    dispute_window: Option<u16>,
+    dispute_timeout: Option<(u16, bool)>,
+    authorization_expiry: Option<u16>,
+    credit_limit: u8,
+    interest_rate: u8,
+    reject_out_of_order: bool,
+    open_on_any_transaction: bool,
+    rows: Vec<Row>,
+}
+
+fn transaction_type(kind: u8) -> TransactionType {
+    match kind % 10 {
+        0 => TransactionType::Deposit,
+        1 => TransactionType::Withdrawal,
+        2 => TransactionType::Dispute,
+        3 => TransactionType::Resolve,
+        4 => TransactionType::Chargeback,
+        5 => TransactionType::Refund,
+        6 => TransactionType::Reversal,
+        7 => TransactionType::Authorize,
+        8 => TransactionType::Capture,
+        _ => TransactionType::Void,
+    }
+}
+
+fn balances(account: &Account) -> (f64, f64, f64, bool) {
+    (account.available, account.held, account.total, account.locked)
+}
+
+fn check_invariants(processor: &TransactionProcessor, clients: &BTreeSet<ClientId>, locked: &mut BTreeMap<ClientId, Account>) {
+    for client in clients {
+        let account = match processor.get_account(client) {
+            Some(account) => account,
+            None => continue,
+        };
+        let sum = account.available + account.held;
+        assert!(
+            (account.total - sum).abs() <= 1e-6 * account.total.abs().max(1.0),
+            "total {} is not available {} + held {}",
+            account.total,
+            account.available,
+            account.held
+        );
+        match locked.get(client) {
+            Some(before) => assert!(balances(before) == balances(account), "locked account {} changed", client),
+            None if account.locked => {
+                locked.insert(*client, *account);
+            }
+            None => {}
+        }
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let mut processor = TransactionProcessor::new();
+    processor.set_dispute_window(input.dispute_window.map(u64::from));
+    processor.set_dispute_timeout(input.dispute_timeout.map(|(after, chargeback)| DisputeTimeout {
+        after: u64::from(after),
+        action: if chargeback { TimeoutAction::Chargeback } else { TimeoutAction::Resolve },
+    }));
+    processor.set_authorization_expiry(input.authorization_expiry.map(u64::from));
+    processor.set_reject_out_of_order(input.reject_out_of_order);
+    processor.set_open_on_any_transaction(input.open_on_any_transaction);
+
+    let mut clients = BTreeSet::new();
+    let mut locked = BTreeMap::new();
+    let mut time: u64 = 0;
+    for row in input.rows {
+        let client = ClientId::from(row.client % 8);
+        if clients.insert(client) {
+            processor.set_credit_line(
+                client,
+                CreditLine {
+                    limit: f64::from(input.credit_limit),
+                    interest_rate: f64::from(input.interest_rate) / 100.0,
+                },
+            );
+        }
+        let timestamp = row.delay.map(|delay| {
+            time += u64::from(delay);
+            time
+        });
+        let transaction = Transaction {
+            transaction_type: transaction_type(row.kind),
+            client,
+            tx: u64::from(row.tx % 32),
+            amount: row.amount.map(|cents| f64::from(cents) / 100.0),
+            disputed: false,
+            timestamp,
+        };
+        let _ = processor.process_transaction(transaction);
+        check_invariants(&processor, &clients, &mut locked);
+    }
+    processor.evict_expired_transactions();
+    processor.finish(Some(time + 1_000_000));
+    check_invariants(&processor, &clients, &mut locked);
+});
@@ -0,0 +1,523 @@
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use rust_tx::input::FileOrder;
+use rust_tx::transaction::parse_timestamp;
+use rust_tx::transaction_processor::TimeoutAction;
+use std::{fs, path::PathBuf};
+
+#[derive(Parser)]
+#[command(name = "rust_tx", version, about = "Processes payment transactions into account balances")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// File of `option = value` lines, used as defaults for the command's options
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Process transactions and print the account balances (the default)
+    Process(ProcessArgs),
+    /// Read the inputs without processing them, reporting every row that can't be parsed
+    Validate(InputArgs),
+    /// Process transactions and print every change to an account
+    Statement(RunArgs),
+    /// Process transactions and print the accounts as a clients file to open a later run with
+    Snapshot(RunArgs),
+    /// Compare two account or snapshot files, or replay the inputs through two configurations, client by client
+    Diff(DiffArgs),
+    /// Generate a synthetic stream of transactions
+    Gen(GenArgs),
+    /// Process transactions and compare the accounts with expected closing balances
+    Reconcile(ReconcileArgs),
+}
+
+#[derive(Args)]
+pub struct InputArgs {
+    /// Files, directories or globs to read, `-` for stdin
+    #[arg(required = true, value_name = "INPUT")]
+    pub inputs: Vec<String>,
+
+    /// Order of the input files, otherwise the order they are given in
+    #[arg(long, value_parser = FileOrder::parse, value_name = "lexical|mtime|sequence")]
+    pub order: Option<FileOrder>,
+
+    /// Write rows read, processed and rejected for each file
+    #[arg(long, value_name = "FILE")]
+    pub stats: Option<PathBuf>,
+
+    /// Write the file, line and reason of every row that wasn't processed
+    #[arg(long, value_name = "FILE")]
+    pub rejects: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct PolicyArgs {
+    /// Limits and fraud rules
+    // `--limits` was its name before fraud rules were added
+    #[arg(long, alias = "limits", value_name = "FILE")]
+    pub rules: Option<PathBuf>,
+
+    /// Clients with their opening balances, account types, credit lines and limits
+    #[arg(long, value_name = "FILE")]
+    pub clients: Option<PathBuf>,
+
+    /// Reject transactions for clients that are not in the clients file
+    #[arg(long)]
+    pub strict_clients: bool,
+
+    /// Open an account for any row of a client, even one that changes nothing
+    #[arg(long)]
+    pub legacy_accounts: bool,
+
+    /// Reject rows older than the latest event time
+    #[arg(long)]
+    pub reject_out_of_order: bool,
+
+    /// Hold rows back to put them in event time order
+    #[arg(long, value_name = "MILLISECONDS")]
+    pub reorder_window: Option<u64>,
+
+    /// How long after a transaction it can still be disputed
+    #[arg(long, value_name = "DAYS")]
+    pub dispute_window: Option<u64>,
+
+    /// Settle disputes left open for longer than this
+    #[arg(long, value_name = "DAYS")]
+    pub dispute_timeout: Option<u64>,
+
+    /// How timed out disputes are settled
+    #[arg(long, value_parser = parse_timeout_action, default_value = "resolve", value_name = "resolve|chargeback")]
+    pub timeout_action: TimeoutAction,
+
+    /// Void authorizations that still hold funds after this
+    #[arg(long, value_name = "DAYS")]
+    pub authorization_expiry: Option<u64>,
+
+    /// The end of the input, for timeouts, expiries and interest
+    #[arg(long, value_parser = parse_timestamp, value_name = "TIMESTAMP")]
+    pub as_of: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    #[command(flatten)]
+    pub policy: PolicyArgs,
+
+    /// Write alerts raised by rules, otherwise they go to stderr
+    #[arg(long, value_name = "FILE")]
+    pub alerts: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ProcessArgs {
+    #[command(flatten)]
+    pub run: RunArgs,
+
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    pub format: Format,
+
+    /// Also write every change to an account
+    #[arg(long, value_name = "FILE")]
+    pub statement: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct DiffArgs {
+    #[arg(required_unless_present = "replay", conflicts_with = "replay")]
+    pub left: Option<PathBuf>,
+    #[arg(required_unless_present = "replay")]
+    pub right: Option<PathBuf>,
+
+    /// Process these inputs through both sides instead of comparing two files
+    #[arg(long, num_args = 1.., value_name = "INPUT")]
+    pub replay: Vec<String>,
+
+    /// Order of the replayed files, otherwise the order they are given in
+    #[arg(long, value_parser = FileOrder::parse, value_name = "lexical|mtime|sequence")]
+    pub order: Option<FileOrder>,
+
+    /// Config file with the policy options of the left side
+    #[arg(long, value_name = "FILE")]
+    pub left_config: Option<PathBuf>,
+
+    /// Config file with the policy options of the right side
+    #[arg(long, value_name = "FILE")]
+    pub right_config: Option<PathBuf>,
+
+    /// Clients file or snapshot the left side opens with, instead of the one in its config
+    #[arg(long, value_name = "FILE")]
+    pub left_clients: Option<PathBuf>,
+
+    /// Clients file or snapshot the right side opens with, instead of the one in its config
+    #[arg(long, value_name = "FILE")]
+    pub right_clients: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct GenArgs {
+    #[arg(long, default_value_t = 1000)]
+    pub rows: u64,
+
+    #[arg(long, default_value_t = 100)]
+    pub clients: u64,
+
+    /// Share of rows that are withdrawals
+    #[arg(long, value_parser = parse_rate, default_value_t = 0.3, value_name = "RATE")]
+    pub withdrawal_rate: f64,
+
+    /// Share of rows that open or resolve disputes
+    #[arg(long, value_parser = parse_rate, default_value_t = 0.01, value_name = "RATE")]
+    pub dispute_rate: f64,
+
+    /// Share of rows that charge back an open dispute
+    #[arg(long, value_parser = parse_rate, default_value_t = 0.0, value_name = "RATE")]
+    pub chargeback_rate: f64,
+
+    /// Share of deposits and withdrawals with a missing, negative or not a number amount
+    #[arg(long, value_parser = parse_rate, default_value_t = 0.0, value_name = "RATE")]
+    pub invalid_rate: f64,
+
+    /// How much rows favor low client ids, 0 spreads them evenly
+    #[arg(long, default_value_t = 0.0)]
+    pub skew: f64,
+
+    #[arg(long, default_value_t = 1)]
+    pub seed: u64,
+
+    /// Also write the accounts the stream should end with
+    #[arg(long, value_name = "FILE")]
+    pub expected: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ReconcileArgs {
+    #[command(flatten)]
+    pub run: RunArgs,
+
+    /// Closing balances to compare with, as `client, available, held, total, locked`
+    #[arg(long, value_name = "FILE")]
+    pub expected: PathBuf,
+
+    /// How far apart amounts can be and still match
+    #[arg(long, value_parser = parse_tolerance, default_value_t = 0.0, value_name = "AMOUNT")]
+    pub tolerance: f64,
+}
+
+// the policy options alone, for each side of a replay
+#[derive(Parser)]
+struct Policy {
+    #[command(flatten)]
+    policy: PolicyArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+fn parse_timeout_action(item: &str) -> Result<TimeoutAction, String> {
+    match item {
+        "resolve" => Ok(TimeoutAction::Resolve),
+        "chargeback" => Ok(TimeoutAction::Chargeback),
+        _ => Err("timeout action must be resolve or chargeback".into()),
+    }
+}
+
+// a share of rows, between 0 and 1
+fn parse_rate(item: &str) -> Result<f64, String> {
+    match item.parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => Err("rate must be between 0 and 1".into()),
+    }
+}
+
+// an amount, zero or above
+fn parse_tolerance(item: &str) -> Result<f64, String> {
+    match item.parse::<f64>() {
+        Ok(tolerance) if tolerance.is_finite() && tolerance >= 0.0 => Ok(tolerance),
+        _ => Err("tolerance must be an amount of zero or more".into()),
+    }
+}
+
+const COMMANDS: [&str; 7] = ["process", "validate", "statement", "snapshot", "diff", "gen", "reconcile"];
+
+// arguments that are handled before any command
+fn is_top_level(arg: &str) -> bool {
+    COMMANDS.contains(&arg) || matches!(arg, "help" | "-h" | "--help" | "-V" | "--version")
+}
+
+/*
+    Turns the lines of a config file into options for `target`. Lines are
+    `option = value`, with the option's long name, and `true` or `false` for
+    flags. Blank lines and lines starting with `#` are skipped.
+*/
+fn config_args(path: &str, target: Option<&clap::Command>) -> Result<Vec<String>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => return Err(format!("unable to read config {}: {}", path, err)),
+    };
+    // an option goes by its long name or any alias of it
+    let named = |arg: &clap::Arg, name: &str| {
+        arg.get_long() == Some(name) || arg.get_all_aliases().is_some_and(|aliases| aliases.contains(&name))
+    };
+    let cli = Cli::command();
+    let known = |name: &str| {
+        cli.get_subcommands()
+            .flat_map(|subcommand| subcommand.get_arguments())
+            .any(|arg| named(arg, name))
+    };
+
+    let mut args = Vec::new();
+    for (line, text) in contents.lines().enumerate() {
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let (name, value) = match text.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => return Err(format!("config line {}: expected option = value", line + 1)),
+        };
+        if !known(name) || name == "config" {
+            return Err(format!("config line {}: unknown option {}", line + 1, name));
+        }
+        // options of other commands are skipped, so one file can serve them all
+        let arg = match target.and_then(|target| target.get_arguments().find(|arg| named(arg, name))) {
+            Some(arg) => arg,
+            None => continue,
+        };
+        if arg.get_action().takes_values() {
+            args.push(format!("--{}", name));
+            args.push(value.to_string());
+        } else {
+            match value {
+                "true" => args.push(format!("--{}", name)),
+                "false" => {}
+                _ => return Err(format!("config line {}: {} must be true or false", line + 1, name)),
+            }
+        }
+    }
+    Ok(args)
+}
+
+// where the command is, after any global options that come before it
+fn command_index(args: &[String]) -> usize {
+    let mut index = 1;
+    while let Some(arg) = args.get(index) {
+        match arg.as_str() {
+            "--config" => index += 2,
+            arg if arg.starts_with("--config=") => index += 1,
+            _ => break,
+        }
+    }
+    index
+}
+
+// the file given to `--config`, as `--config FILE` or `--config=FILE`
+fn config_path(args: &[String]) -> Option<&str> {
+    args.iter().enumerate().find_map(|(index, arg)| match arg.strip_prefix("--config") {
+        Some("") => args.get(index + 1).map(|path| path.as_str()),
+        Some(path) => path.strip_prefix('='),
+        None => None,
+    })
+}
+
+/*
+    Parses the command line. Without a command the arguments are for
+    `process`, as in earlier versions. Options from `--config` go before the
+    ones on the command line, which take precedence.
+*/
+pub fn parse(mut args: Vec<String>) -> Result<Cli, clap::Error> {
+    let index = command_index(&args);
+    if args.len() > 1 && !args.get(index).is_some_and(|arg| is_top_level(arg)) {
+        args.insert(index, "process".into());
+    }
+    if let (Some(path), Some(command)) = (config_path(&args), args.get(index)) {
+        let cli = Cli::command();
+        match config_args(path, cli.find_subcommand(command)) {
+            Ok(config) => {
+                args.splice(index + 1..index + 1, config);
+            }
+            Err(err) => return Err(Cli::command().error(clap::error::ErrorKind::InvalidValue, err)),
+        }
+    }
+    let command = Cli::command().mut_subcommands(|subcommand| subcommand.args_override_self(true));
+    let matches = command.try_get_matches_from(args)?;
+    Cli::from_arg_matches(&matches)
+}
+
+// the policy in a config file, or the defaults without one
+pub fn load_policy(path: Option<&PathBuf>) -> Result<PolicyArgs, String> {
+    let mut args = vec!["config".to_string()];
+    if let Some(path) = path {
+        let path = path.to_str().ok_or_else(|| format!("config {} is not valid UTF-8", path.display()))?;
+        args.extend(config_args(path, Some(&Policy::command()))?);
+    }
+    match Policy::try_parse_from(args) {
+        Ok(policy) => Ok(policy.policy),
+        Err(err) => {
+            // only the first line of clap's message, without its `error: ` and usage
+            let message = err.to_string();
+            let message = message.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string();
+            Err(format!("invalid config {}: {}", path.map_or(String::new(), |path| path.display().to_string()), message))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_policy, parse, Command, Format};
+    use rust_tx::transaction_processor::TimeoutAction;
+    use std::fs;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn should_process_when_no_command_is_given() {
+        let cli = parse(args("rust_tx --dispute-window 30 transactions.csv")).unwrap();
+        match cli.command {
+            Command::Process(args) => {
+                assert!(args.run.input.inputs == vec!["transactions.csv"]);
+                assert!(args.run.policy.dispute_window == Some(30));
+            }
+            _ => panic!("expected process"),
+        }
+        assert!(parse(args("rust_tx")).is_err());
+        assert!(parse(args("rust_tx process")).is_err());
+    }
+
+    #[test]
+    fn command_line_should_take_precedence_over_config() {
+        let path = std::env::temp_dir().join(format!("rust_tx_config_{}", std::process::id()));
+        fs::write(&path, "# defaults\nformat = json\ntimeout-action = chargeback\nstrict-clients = true\n").unwrap();
+        let config = path.to_str().unwrap();
+
+        let cli = parse(args(&format!("rust_tx process --config {} --format csv in.csv", config))).unwrap();
+        match cli.command {
+            Command::Process(args) => {
+                assert!(args.format == Format::Csv);
+                assert!(args.run.policy.timeout_action == TimeoutAction::Chargeback);
+                assert!(args.run.policy.strict_clients);
+            }
+            _ => panic!("expected process"),
+        }
+        // `format` is only an option of process
+        assert!(parse(args(&format!("rust_tx validate --config {} in.csv", config))).is_ok());
+
+        fs::write(&path, "dispute-windw = 30\n").unwrap();
+        assert!(parse(args(&format!("rust_tx --config {} in.csv", config))).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn limits_should_still_name_the_rules_file() {
+        let cli = parse(args("rust_tx --limits limits.csv in.csv")).unwrap();
+        match cli.command {
+            Command::Process(args) => assert!(args.run.policy.rules.unwrap().to_str() == Some("limits.csv")),
+            _ => panic!("expected process"),
+        }
+
+        let path = std::env::temp_dir().join(format!("rust_tx_limits_{}", std::process::id()));
+        fs::write(&path, "limits = limits.csv\n").unwrap();
+        let policy = load_policy(Some(&path)).unwrap();
+        assert!(policy.rules.unwrap().to_str() == Some("limits.csv"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn global_options_should_be_allowed_before_the_command() {
+        let path = std::env::temp_dir().join(format!("rust_tx_global_{}", std::process::id()));
+        fs::write(&path, "dispute-window = 30\n").unwrap();
+        let config = path.to_str().unwrap();
+
+        let cli = parse(args(&format!("rust_tx --config {} validate in.csv", config))).unwrap();
+        assert!(matches!(cli.command, Command::Validate(_)));
+        assert!(cli.config.is_some());
+        // without a command, the arguments after the global options are for process
+        for line in [format!("rust_tx --config {} in.csv", config), format!("rust_tx --config={} in.csv", config)] {
+            match parse(args(&line)).unwrap().command {
+                Command::Process(args) => {
+                    assert!(args.run.input.inputs == vec!["in.csv"]);
+                    assert!(args.run.policy.dispute_window == Some(30));
+                }
+                _ => panic!("expected process"),
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn gen_should_only_take_rates_between_0_and_1() {
+        let cli = parse(args("rust_tx gen --rows 10 --dispute-rate 0.2 --skew 1.5")).unwrap();
+        match cli.command {
+            Command::Gen(args) => {
+                assert!(args.rows == 10 && args.dispute_rate == 0.2 && args.skew == 1.5);
+                assert!(args.withdrawal_rate == 0.3 && args.expected.is_none());
+            }
+            _ => panic!("expected gen"),
+        }
+        assert!(parse(args("rust_tx gen --invalid-rate 1.5")).is_err());
+        assert!(parse(args("rust_tx gen --chargeback-rate -0.1")).is_err());
+    }
+
+    #[test]
+    fn diff_should_take_two_files_or_a_replay() {
+        let cli = parse(args("rust_tx diff left.csv right.csv")).unwrap();
+        match cli.command {
+            Command::Diff(args) => assert!(args.left.is_some() && args.right.is_some() && args.replay.is_empty()),
+            _ => panic!("expected diff"),
+        }
+        let cli = parse(args("rust_tx diff --left-config a.conf --right-clients b.csv --replay in.csv more/")).unwrap();
+        match cli.command {
+            Command::Diff(args) => {
+                assert!(args.left.is_none() && args.replay == vec!["in.csv", "more/"]);
+                assert!(args.left_config.is_some() && args.right_clients.is_some());
+            }
+            _ => panic!("expected diff"),
+        }
+        assert!(parse(args("rust_tx diff left.csv")).is_err());
+        assert!(parse(args("rust_tx diff left.csv right.csv --replay in.csv")).is_err());
+    }
+
+    #[test]
+    fn load_policy_should_only_take_policy_options() {
+        let path = std::env::temp_dir().join(format!("rust_tx_policy_{}", std::process::id()));
+        fs::write(&path, "dispute-window = 30
+legacy-accounts = true
+format = json
+").unwrap();
+        let policy = load_policy(Some(&path)).unwrap();
+        assert!(policy.dispute_window == Some(30) && policy.legacy_accounts);
+        assert!(policy.timeout_action == TimeoutAction::Resolve);
+
+        let defaults = load_policy(None).unwrap();
+        assert!(defaults.dispute_window.is_none() && !defaults.legacy_accounts);
+
+        fs::write(&path, "dispute-window = soon
+").unwrap();
+        assert!(load_policy(Some(&path)).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reconcile_should_need_expected_balances_and_a_tolerance_of_zero_or_more() {
+        let cli = parse(args("rust_tx reconcile --expected closing.csv --tolerance 0.01 --dispute-window 30 in.csv")).unwrap();
+        match cli.command {
+            Command::Reconcile(args) => {
+                assert!(args.tolerance == 0.01 && args.expected.to_str() == Some("closing.csv"));
+                assert!(args.run.policy.dispute_window == Some(30) && args.run.input.inputs == vec!["in.csv"]);
+            }
+            _ => panic!("expected reconcile"),
+        }
+        assert!(parse(args("rust_tx reconcile in.csv")).is_err());
+        assert!(parse(args("rust_tx reconcile --expected closing.csv --tolerance -1 in.csv")).is_err());
+        assert!(parse(args("rust_tx reconcile --expected closing.csv --tolerance NaN in.csv")).is_err());
+    }
+}
@@ -1,30 +1,88 @@
+use super::{clients::AccountType, transaction::ClientId};
 use std::ops::{AddAssign, SubAssign};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Account {
     // client, available, held, total, locked
-    pub client: u16,
+    pub client: ClientId,
     pub available: f64,
     pub held: f64,
     pub total: f64,
     pub locked: bool,
+    // how far below zero `available` may go
+    pub credit_limit: f64,
+    // yearly rate charged on a negative `available`
+    pub interest_rate: f64,
+    // event time interest was last charged up to, none until the account has seen one
+    pub accrued_at: Option<u64>,
+    // interest charged and not yet paid off, which is not charged interest itself
+    pub interest_owed: f64,
+    pub account_type: AccountType,
 }
 
+const MILLIS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0 * 1000.0;
+
 impl Account {
-    pub fn new(client: u16) -> Self {
-        return Account {
+    pub fn new(client: ClientId) -> Self {
+        Account {
             client,
             available: 0.0,
             held: 0.0,
             total: 0.0,
             locked: false,
+            credit_limit: 0.0,
+            interest_rate: 0.0,
+            accrued_at: None,
+            interest_owed: 0.0,
+            account_type: AccountType::default(),
+        }
+    }
+
+    // what can still be withdrawn, including the credit line
+    pub fn headroom(&self) -> f64 {
+        if self.locked {
+            return 0.0;
+        }
+        (self.available + self.credit_limit).max(0.0)
+    }
+
+    /*
+        Charges simple interest on the overdrawn principal for the time since
+        it was last charged, so what is charged only depends on the balance and
+        how long it was held, however often this is called. The first call
+        only starts the clock.
+    */
+    pub fn accrue_interest(&mut self, now: u64) {
+        let accrued_at = match self.accrued_at {
+            Some(accrued_at) => accrued_at,
+            None => {
+                self.accrued_at = Some(now);
+                return;
+            }
         };
+        if now <= accrued_at {
+            return;
+        }
+        let principal = -self.available - self.interest_owed;
+        if principal > 0.0 && self.interest_rate > 0.0 && !self.locked {
+            let years = (now - accrued_at) as f64 / MILLIS_PER_YEAR;
+            let interest = principal * self.interest_rate * years;
+            self.available.sub_assign(interest);
+            self.total.sub_assign(interest);
+            self.interest_owed.add_assign(interest);
+        }
+        self.accrued_at = Some(now);
+    }
+
+    // money coming back into `available` pays off the interest owed first
+    fn pay_interest(&mut self, amount: f64) {
+        self.interest_owed = (self.interest_owed - amount).max(0.0);
     }
 
-    pub fn deposit(&mut self, amount: f64) {
+    pub fn deposit(&mut self, amount: f64) -> bool {
         // account locked, we do not process this transaction
         if self.locked {
-            return;
+            return false;
         }
         
         /*
@@ -36,51 +94,88 @@ impl Account {
         */
         self.available.add_assign(amount);
         self.total.add_assign(amount);
+        self.pay_interest(amount);
+        true
     }
 
-    pub fn withdraw(&mut self, amount: f64) {
+    pub fn withdraw(&mut self, amount: f64) -> bool {
         if self.locked {
-            return;
+            return false;
         }
-        if self.available >= amount {
+        if self.available + self.credit_limit >= amount {
             self.available.sub_assign(amount);
             self.total.sub_assign(amount);
+            return true;
         }
         // not enough funds
+        false
     }
 
-    pub fn dispute(&mut self, amount: f64) {
+    pub fn dispute(&mut self, amount: f64) -> bool {
         if self.locked {
-            return;
+            return false;
         }
         self.available.sub_assign(amount);
         self.held.add_assign(amount);
         // TODO: At this point, what if the available amount is negative??
+        true
     }
 
-    pub fn resolve(&mut self, amount: f64) {
+    pub fn resolve(&mut self, amount: f64) -> bool {
         if self.locked {
-            return;
+            return false;
         }
         // TODO: held must be greater than or equal to amount  ??
         // TODO: do we handle disputed transactions for deposits AND withdrawals ?
         self.available.add_assign(amount);
         self.held.sub_assign(amount);
+        self.pay_interest(amount);
+        true
+    }
+
+    // authorizations hold funds until they are captured or voided
+    pub fn authorize(&mut self, amount: f64) -> bool {
+        if self.locked || self.available + self.credit_limit < amount {
+            return false;
+        }
+        self.available.sub_assign(amount);
+        self.held.add_assign(amount);
+        true
     }
 
-    pub fn chargeback(&mut self, amount: f64) {
+    pub fn capture(&mut self, amount: f64) -> bool {
         if self.locked {
-            return;
+            return false;
+        }
+        self.held.sub_assign(amount);
+        self.total.sub_assign(amount);
+        true
+    }
+
+    pub fn void(&mut self, amount: f64) -> bool {
+        if self.locked {
+            return false;
+        }
+        self.held.sub_assign(amount);
+        self.available.add_assign(amount);
+        self.pay_interest(amount);
+        true
+    }
+
+    pub fn chargeback(&mut self, amount: f64) -> bool {
+        if self.locked {
+            return false;
         }
         self.locked = true;
         self.total.sub_assign(amount);
         self.held.sub_assign(amount);
+        true
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Account;
+    use super::{Account, MILLIS_PER_YEAR};
 
     #[test]
     fn should_increment_funds_after_deposit() {
@@ -112,6 +207,60 @@ mod tests {
         assert!(account.total == (1000.0 - amount));
     }
 
+    #[test]
+    fn withdraw_should_use_credit_line() {
+        let mut account = Account::new(100);
+        account.credit_limit = 500.0;
+        account.deposit(100.0);
+
+        assert!(account.withdraw(400.0));
+        assert!(account.available == -300.0);
+        assert!(account.headroom() == 200.0);
+        assert!(!account.withdraw(300.0));
+    }
+
+    #[test]
+    fn accrue_interest_should_only_charge_overdrawn_balance() {
+        let mut account = Account::new(100);
+        account.interest_rate = 0.1;
+        account.deposit(100.0);
+        account.accrue_interest(MILLIS_PER_YEAR as u64);
+        assert!(account.available == 100.0);
+
+        account.credit_limit = 1000.0;
+        account.withdraw(1100.0);
+        account.accrue_interest(2 * MILLIS_PER_YEAR as u64);
+        assert!((account.available + 1100.0).abs() < 1e-9);
+        assert!((account.total - account.available).abs() < 1e-9);
+    }
+
+    #[test]
+    fn accrue_interest_should_not_depend_on_how_often_it_is_charged() {
+        let year = MILLIS_PER_YEAR as u64;
+        let overdrawn = || {
+            let mut account = Account::new(100);
+            account.interest_rate = 0.5;
+            account.credit_limit = 1000.0;
+            account.accrue_interest(0);
+            account.withdraw(100.0);
+            account
+        };
+        let mut once = overdrawn();
+        once.accrue_interest(year);
+        let mut monthly = overdrawn();
+        for month in 1..=12 {
+            monthly.accrue_interest(year * month / 12);
+        }
+        assert!((once.available + 150.0).abs() < 1e-9);
+        assert!((monthly.available - once.available).abs() < 1e-9);
+
+        // a repayment goes to the interest first, the principal left is still 100
+        monthly.deposit(30.0);
+        assert!((monthly.interest_owed - 20.0).abs() < 1e-9);
+        monthly.accrue_interest(2 * year);
+        assert!((monthly.available + 170.0).abs() < 1e-9);
+    }
+
     #[test]
     fn dispute_should_increase_held_and_decrease_available_funds() {
         let mut account = Account::new(100);
@@ -170,6 +319,24 @@ mod tests {
         assert!(account.held == 0.0);
     }
 
+    #[test]
+    pub fn authorize_should_hold_funds_until_captured_or_voided() {
+        let mut account = Account::new(100);
+        account.deposit(1000.0);
+        assert!(!account.authorize(2000.0));
+
+        assert!(account.authorize(300.0));
+        assert!(account.available == 700.0);
+        assert!(account.held == 300.0);
+        assert!(account.total == 1000.0);
+
+        account.capture(200.0);
+        account.void(100.0);
+        assert!(account.available == 800.0);
+        assert!(account.held == 0.0);
+        assert!(account.total == 800.0);
+    }
+
     #[test]
     pub fn deposit_is_ignored_if_account_is_locked() {
         let mut account = Account::new(100);
@@ -0,0 +1,256 @@
+use super::{
+    rules::{Limit, LimitRule, RuleAction},
+    transaction::{parse_id, ClientId},
+};
+use csv::{StringRecord, Trim};
+use std::{fmt, path::Path};
+
+// a line of credit that lets `available` go as low as -limit
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CreditLine {
+    pub limit: f64,
+    // yearly rate charged on the overdrawn balance
+    pub interest_rate: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AccountType {
+    #[default]
+    Personal,
+    Business,
+    Merchant,
+}
+
+impl AccountType {
+    fn parse(item: &str) -> Result<AccountType, String> {
+        match item {
+            "personal" => Ok(AccountType::Personal),
+            "business" => Ok(AccountType::Business),
+            "merchant" => Ok(AccountType::Merchant),
+            _ => Err("Not a valid account type".into()),
+        }
+    }
+}
+
+impl fmt::Display for AccountType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AccountType::Personal => "personal",
+            AccountType::Business => "business",
+            AccountType::Merchant => "merchant",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// a client declared up front, with the state its account opens in
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ClientConfig {
+    pub client: ClientId,
+    pub account_type: AccountType,
+    pub available: f64,
+    pub held: f64,
+    pub locked: bool,
+    pub credit_line: CreditLine,
+    pub max_amount: Option<f64>,
+    pub daily_withdrawal: Option<f64>,
+}
+
+impl ClientConfig {
+    // the client's own limits, rejecting transactions that breach them
+    pub fn limit_rules(&self) -> Vec<LimitRule> {
+        let limits = [
+            self.max_amount.map(Limit::MaxAmount),
+            self.daily_withdrawal.map(Limit::MaxDailyWithdrawal),
+        ];
+        limits
+            .iter()
+            .flatten()
+            .map(|limit| LimitRule {
+                client: Some(self.client),
+                limit: *limit,
+                action: RuleAction::Reject,
+            })
+            .collect()
+    }
+}
+
+// position of each known column in the header, columns other than client are optional
+struct Columns {
+    client: usize,
+    account_type: Option<usize>,
+    available: Option<usize>,
+    held: Option<usize>,
+    locked: Option<usize>,
+    credit_limit: Option<usize>,
+    interest_rate: Option<usize>,
+    max_amount: Option<usize>,
+    daily_withdrawal: Option<usize>,
+}
+
+impl Columns {
+    fn parse(headers: &StringRecord) -> Result<Columns, String> {
+        let position = |name: &str| headers.iter().position(|header| header == name);
+        Ok(Columns {
+            client: match position("client") {
+                Some(index) => index,
+                None => return Err("clients file has no client column".into()),
+            },
+            account_type: position("account_type"),
+            available: position("available"),
+            held: position("held"),
+            locked: position("locked"),
+            credit_limit: position("credit_limit"),
+            interest_rate: position("interest_rate"),
+            max_amount: position("max_amount"),
+            daily_withdrawal: position("daily_withdrawal"),
+        })
+    }
+}
+
+// None for a missing column or an empty cell
+fn field(record: &StringRecord, column: Option<usize>) -> Option<&str> {
+    match column.and_then(|index| record.get(index)) {
+        None | Some("") => None,
+        Some(value) => Some(value),
+    }
+}
+
+fn parse_amount(record: &StringRecord, column: Option<usize>, name: &str) -> Result<Option<f64>, String> {
+    match field(record, column) {
+        None => Ok(None),
+        Some(value) => match value.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(Some(value)),
+            _ => Err(format!("unable to parse {}", name)),
+        },
+    }
+}
+
+fn parse_positive(record: &StringRecord, column: Option<usize>, name: &str) -> Result<Option<f64>, String> {
+    match parse_amount(record, column, name)? {
+        Some(value) if value < 0.0 => Err(format!("{} can't be negative", name)),
+        value => Ok(value),
+    }
+}
+
+impl ClientConfig {
+    fn parse(record: &StringRecord, columns: &Columns) -> Result<ClientConfig, String> {
+        let client: ClientId = match record.get(columns.client) {
+            Some(id) => parse_id(id, "client")?,
+            None => return Err("unable to parse client".into()),
+        };
+        let account_type = match field(record, columns.account_type) {
+            Some(account_type) => AccountType::parse(account_type)?,
+            None => AccountType::default(),
+        };
+        let locked = match field(record, columns.locked) {
+            Some("true") => true,
+            Some("false") | None => false,
+            Some(_) => return Err("unable to parse locked".into()),
+        };
+        Ok(ClientConfig {
+            client,
+            account_type,
+            // an opening balance below zero draws on the credit line
+            available: parse_amount(record, columns.available, "available")?.unwrap_or(0.0),
+            held: parse_positive(record, columns.held, "held")?.unwrap_or(0.0),
+            locked,
+            credit_line: CreditLine {
+                limit: parse_positive(record, columns.credit_limit, "credit_limit")?.unwrap_or(0.0),
+                interest_rate: parse_positive(record, columns.interest_rate, "interest_rate")?
+                    .unwrap_or(0.0),
+            },
+            max_amount: parse_positive(record, columns.max_amount, "max_amount")?,
+            daily_withdrawal: parse_positive(record, columns.daily_withdrawal, "daily_withdrawal")?,
+        })
+    }
+}
+
+pub fn load_clients<P: AsRef<Path>>(path: P) -> Result<Vec<ClientConfig>, String> {
+    let mut reader = match csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(Trim::All)
+        .from_path(path)
+    {
+        Ok(reader) => reader,
+        Err(err) => return Err(format!("unable to read clients: {}", err)),
+    };
+    let columns = match reader.headers() {
+        Ok(headers) => Columns::parse(headers)?,
+        Err(err) => return Err(format!("unable to read clients: {}", err)),
+    };
+
+    let mut clients = Vec::new();
+    for (line, result) in reader.records().enumerate() {
+        let record = match result {
+            Ok(record) => record,
+            Err(err) => return Err(format!("unable to read clients: {}", err)),
+        };
+        match ClientConfig::parse(&record, &columns) {
+            Ok(client) => clients.push(client),
+            // the header is line 1
+            Err(err) => return Err(format!("line {}: {}", line + 2, err)),
+        }
+    }
+    Ok(clients)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccountType, ClientConfig, Columns};
+    use crate::rules::Limit;
+    use csv::StringRecord;
+
+    #[test]
+    fn should_parse_client_with_columns_in_any_order() {
+        let headers = StringRecord::from(vec!["interest_rate", "client", "credit_limit"]);
+        let columns = Columns::parse(&headers).unwrap();
+        let record = StringRecord::from(vec!["0.2", "100", "500"]);
+        let config = ClientConfig::parse(&record, &columns).unwrap();
+        assert!(config.client == 100);
+        assert!(config.credit_line.limit == 500.0);
+        assert!(config.credit_line.interest_rate == 0.2);
+    }
+
+    #[test]
+    fn should_default_missing_columns_to_zero() {
+        let headers = StringRecord::from(vec!["client", "credit_limit"]);
+        let columns = Columns::parse(&headers).unwrap();
+        let config = ClientConfig::parse(&StringRecord::from(vec!["100", ""]), &columns).unwrap();
+        assert!(config.credit_line.limit == 0.0);
+        assert!(config.credit_line.interest_rate == 0.0);
+        assert!(config.account_type == AccountType::Personal);
+        assert!(!config.locked);
+        assert!(config.max_amount.is_none());
+    }
+
+    #[test]
+    fn should_parse_opening_state_and_limits() {
+        let headers = StringRecord::from(vec![
+            "client", "account_type", "available", "held", "locked", "max_amount", "daily_withdrawal",
+        ]);
+        let columns = Columns::parse(&headers).unwrap();
+        let record = StringRecord::from(vec!["100", "business", "250.5", "10", "true", "1000", ""]);
+        let config = ClientConfig::parse(&record, &columns).unwrap();
+        assert!(config.account_type == AccountType::Business);
+        assert!(config.available == 250.5);
+        assert!(config.held == 10.0);
+        assert!(config.locked);
+
+        let rules = config.limit_rules();
+        assert!(rules.len() == 1);
+        assert!(rules[0].client == Some(100));
+        assert!(rules[0].limit == Limit::MaxAmount(1000.0));
+    }
+
+    #[test]
+    fn should_return_err_if_invalid_client() {
+        let headers = StringRecord::from(vec!["client", "credit_limit", "account_type"]);
+        let columns = Columns::parse(&headers).unwrap();
+        assert!(ClientConfig::parse(&StringRecord::from(vec!["18446744073709551616", "5", ""]), &columns).is_err());
+        assert!(ClientConfig::parse(&StringRecord::from(vec!["x", "5", ""]), &columns).is_err());
+        assert!(ClientConfig::parse(&StringRecord::from(vec!["100", "-5", ""]), &columns).is_err());
+        assert!(ClientConfig::parse(&StringRecord::from(vec!["100", "5", "savings"]), &columns).is_err());
+        assert!(Columns::parse(&StringRecord::from(vec!["id"])).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use super::{account::Account, clients::ClientConfig, transaction::ClientId};
+use std::collections::BTreeMap;
+
+// a client whose balances differ between two account files
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub client: ClientId,
+    pub field: &'static str,
+    pub left: String,
+    pub right: String,
+}
+
+// four decimal places, where a balance that rounds to zero is zero whatever its sign
+pub(crate) fn decimal(value: f64) -> String {
+    let text = format!("{:.4}", value);
+    match text.as_str() {
+        "-0.0000" => "0.0000".into(),
+        _ => text,
+    }
+}
+
+// the fields of an account that are compared, as they are written out
+fn fields(available: f64, held: f64, locked: bool) -> [(&'static str, String); 4] {
+    [
+        ("available", decimal(available)),
+        ("held", decimal(held)),
+        ("total", decimal(available + held)),
+        ("locked", locked.to_string()),
+    ]
+}
+
+fn compare(client: ClientId, left: Option<(f64, f64, bool)>, right: Option<(f64, f64, bool)>, differences: &mut Vec<Difference>) {
+    match (left, right) {
+        (Some(left), Some(right)) => {
+            for ((field, left), (_, right)) in fields(left.0, left.1, left.2).into_iter().zip(fields(right.0, right.1, right.2)) {
+                if left != right {
+                    differences.push(Difference { client, field, left, right });
+                }
+            }
+        }
+        (None, None) => {}
+        (left, right) => differences.push(Difference {
+            client,
+            field: "client",
+            left: if left.is_some() { "present" } else { "missing" }.into(),
+            right: if right.is_some() { "present" } else { "missing" }.into(),
+        }),
+    }
+}
+
+/*
+    Compares two sets of accounts, read with load_clients from either the
+    output of a run or a snapshot. Balances are compared to the four decimal
+    places they are written with. A client missing from one side is reported
+    once, as a difference in its `client` field.
+*/
+pub fn diff_accounts(left: &[ClientConfig], right: &[ClientConfig]) -> Vec<Difference> {
+    let mut clients: BTreeMap<ClientId, (Option<&ClientConfig>, Option<&ClientConfig>)> = BTreeMap::new();
+    for account in left {
+        clients.entry(account.client).or_default().0 = Some(account);
+    }
+    for account in right {
+        clients.entry(account.client).or_default().1 = Some(account);
+    }
+
+    let balances = |account: Option<&ClientConfig>| account.map(|account| (account.available, account.held, account.locked));
+    let mut differences = Vec::new();
+    for (client, (left, right)) in clients {
+        compare(client, balances(left), balances(right), &mut differences);
+    }
+    differences
+}
+
+// the same comparison for one client's accounts in two processors
+pub fn diff_account(client: ClientId, left: Option<&Account>, right: Option<&Account>) -> Vec<Difference> {
+    let balances = |account: Option<&Account>| account.map(|account| (account.available, account.held, account.locked));
+    let mut differences = Vec::new();
+    compare(client, balances(left), balances(right), &mut differences);
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_account, diff_accounts};
+    use crate::{account::Account, clients::ClientConfig};
+
+    fn account(client: u64, available: f64, locked: bool) -> ClientConfig {
+        ClientConfig { client, available, locked, ..ClientConfig::default() }
+    }
+
+    #[test]
+    fn should_report_changed_fields_and_missing_clients() {
+        let left = vec![account(1, 1.5, false), account(2, 3.0, false), account(3, 0.0, false)];
+        let right = vec![account(1, 1.5, false), account(2, 2.0, true), account(4, 0.0, false)];
+        let differences = diff_accounts(&left, &right);
+
+        let fields: Vec<(u64, &str)> = differences.iter().map(|d| (d.client, d.field)).collect();
+        assert!(fields == vec![(2, "available"), (2, "total"), (2, "locked"), (3, "client"), (4, "client")]);
+        assert!(differences[0].left == "3.0000" && differences[0].right == "2.0000");
+        assert!(differences[3].right == "missing");
+
+        // rounding can leave a balance a hair below zero
+        assert!(diff_accounts(&[account(1, -0.00001, false)], &[account(1, 0.0, false)]).is_empty());
+    }
+
+    #[test]
+    fn should_ignore_differences_below_the_written_precision() {
+        let left = vec![account(1, 1.00001, false)];
+        let right = vec![account(1, 1.0, false)];
+        assert!(diff_accounts(&left, &right).is_empty());
+    }
+
+    #[test]
+    fn diff_account_should_compare_live_accounts() {
+        let mut left = Account::new(1);
+        left.deposit(10.0);
+        let mut right = left;
+        assert!(diff_account(1, Some(&left), Some(&right)).is_empty());
+        assert!(diff_account(1, None, None).is_empty());
+
+        right.dispute(4.0);
+        let fields: Vec<&str> = diff_account(1, Some(&left), Some(&right)).iter().map(|d| d.field).collect();
+        assert!(fields == vec!["available", "held"]);
+        assert!(diff_account(1, Some(&left), None)[0].right == "missing");
+    }
+}
@@ -0,0 +1,92 @@
+use super::transaction::{ClientId, TxId};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProcessError {
+    // a rule with the `reject` action was breached
+    RuleViolation(String),
+    // the row's timestamp is older than the latest event time
+    OutOfOrder { timestamp: u64, latest: u64 },
+    // the disputed transaction is older than the dispute window
+    DisputeWindowExpired(TxId),
+    // the whole transaction is already under dispute
+    AlreadyDisputed(TxId),
+    // a dispute, resolve or chargeback amount outside of (0, limit]
+    InvalidDisputeAmount { tx: TxId, amount: f64, limit: f64 },
+    // nothing is left of the transaction to refund or reverse
+    AlreadyRefunded(TxId),
+    // a refund amount outside of (0, limit]
+    InvalidRefundAmount { tx: TxId, amount: f64, limit: f64 },
+    // a capture amount outside of (0, limit]
+    InvalidCaptureAmount { tx: TxId, amount: f64, limit: f64 },
+    // a deposit, withdrawal or authorize without an amount, or an amount that is negative or not a number
+    InvalidAmount(TxId),
+    // the client is not declared in the clients file and undeclared clients are rejected
+    UnknownClient(ClientId),
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessError::RuleViolation(reason) => write!(f, "rejected by rule: {}", reason),
+            ProcessError::OutOfOrder { timestamp, latest } => {
+                write!(f, "timestamp {} is before {}", timestamp, latest)
+            }
+            ProcessError::DisputeWindowExpired(tx) => {
+                write!(f, "tx {} is outside the dispute window", tx)
+            }
+            ProcessError::AlreadyDisputed(tx) => write!(f, "tx {} is already disputed", tx),
+            ProcessError::InvalidDisputeAmount { tx, amount, limit } => {
+                write!(f, "amount {} for tx {} must be above 0 and at most {}", amount, tx, limit)
+            }
+            ProcessError::AlreadyRefunded(tx) => write!(f, "tx {} has nothing left to refund", tx),
+            ProcessError::InvalidRefundAmount { tx, amount, limit } => {
+                write!(f, "refund of {} for tx {} must be above 0 and at most {}", amount, tx, limit)
+            }
+            ProcessError::InvalidCaptureAmount { tx, amount, limit } => {
+                write!(f, "capture of {} for tx {} must be above 0 and at most {}", amount, tx, limit)
+            }
+            ProcessError::InvalidAmount(tx) => write!(f, "tx {} has a missing or invalid amount", tx),
+            ProcessError::UnknownClient(client) => write!(f, "client {} is not declared", client),
+        }
+    }
+}
+
+impl std::error::Error for ProcessError {}
+
+// why a row couldn't be read as a transaction, without allocating
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseError {
+    // fewer than the type, client and tx columns
+    Row,
+    InvalidUtf8,
+    TransactionType,
+    Client,
+    ClientOutOfRange,
+    Transaction,
+    TransactionOutOfRange,
+    Timestamp,
+}
+
+impl ParseError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParseError::Row => "unable to parse row",
+            ParseError::InvalidUtf8 => "row is not valid utf-8",
+            ParseError::TransactionType => "unable to parse transaction type",
+            ParseError::Client => "unable to parse client",
+            ParseError::ClientOutOfRange => "client id is out of range",
+            ParseError::Transaction => "unable to parse transaction",
+            ParseError::TransactionOutOfRange => "transaction id is out of range",
+            ParseError::Timestamp => "unable to parse timestamp",
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,182 @@
+use super::{
+    account::Account,
+    rules::{applies_to, ClientHistory, Clock, Rule, RuleAction, Verdict},
+    transaction::{ClientId, Transaction, TransactionType},
+};
+
+// share of a client's deposits that end up disputed
+#[derive(Debug, Clone, Copy)]
+pub struct DisputeRatio {
+    pub client: Option<ClientId>,
+    pub threshold: f64,
+    pub action: RuleAction,
+}
+
+impl Rule for DisputeRatio {
+    fn name(&self) -> &'static str {
+        "dispute_ratio"
+    }
+
+    fn evaluate(&self, tx: &Transaction, _account: &Account, history: &ClientHistory, _clock: Clock) -> Verdict {
+        if !applies_to(self.client, tx.client)
+            || !matches!(tx.transaction_type, TransactionType::Dispute)
+            || history.deposits == 0
+        {
+            return Verdict::Allow;
+        }
+        let ratio = (history.disputes + 1) as f64 / history.deposits as f64;
+        if ratio > self.threshold {
+            return self.action.verdict(format!(
+                "{} of {} deposits disputed",
+                history.disputes + 1,
+                history.deposits
+            ));
+        }
+        Verdict::Allow
+    }
+}
+
+// a withdrawal of exactly the amount that was just deposited
+#[derive(Debug, Clone, Copy)]
+pub struct DepositThenWithdrawal {
+    pub client: Option<ClientId>,
+    pub action: RuleAction,
+}
+
+impl Rule for DepositThenWithdrawal {
+    fn name(&self) -> &'static str {
+        "deposit_withdrawal"
+    }
+
+    fn evaluate(&self, tx: &Transaction, _account: &Account, history: &ClientHistory, _clock: Clock) -> Verdict {
+        if !applies_to(self.client, tx.client)
+            || !matches!(tx.transaction_type, TransactionType::Withdrawal)
+        {
+            return Verdict::Allow;
+        }
+        if let Some(last) = history.last() {
+            if matches!(last.transaction.transaction_type, TransactionType::Deposit)
+                && last.transaction.amount == tx.amount
+            {
+                return self.action.verdict(format!(
+                    "withdrawal of the amount deposited by tx {}",
+                    last.transaction.tx
+                ));
+            }
+        }
+        Verdict::Allow
+    }
+}
+
+// too many disputes in a short window
+#[derive(Debug, Clone, Copy)]
+pub struct DisputeBurst {
+    pub client: Option<ClientId>,
+    pub count: usize,
+    pub window: u64,
+    pub action: RuleAction,
+}
+
+impl Rule for DisputeBurst {
+    fn name(&self) -> &'static str {
+        "dispute_burst"
+    }
+
+    fn evaluate(&self, tx: &Transaction, _account: &Account, history: &ClientHistory, clock: Clock) -> Verdict {
+        if !applies_to(self.client, tx.client)
+            || !matches!(tx.transaction_type, TransactionType::Dispute)
+        {
+            return Verdict::Allow;
+        }
+        let disputes = history.count_since(clock.now.saturating_sub(self.window), |t| {
+            matches!(t, TransactionType::Dispute)
+        });
+        if disputes + 1 > self.count {
+            return self.action.verdict(format!(
+                "more than {} disputes within {}",
+                self.count, self.window
+            ));
+        }
+        Verdict::Allow
+    }
+
+    fn window(&self) -> u64 {
+        self.window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DepositThenWithdrawal, DisputeBurst, DisputeRatio};
+    use crate::{
+        account::Account,
+        rules::{Clock, RuleAction, RuleSet},
+        transaction::{Transaction, TransactionType, TxId},
+    };
+
+    fn transaction(transaction_type: TransactionType, tx: TxId, amount: Option<f64>) -> Transaction {
+        Transaction {
+            transaction_type,
+            client: 100,
+            tx,
+            amount,
+            disputed: false,
+            timestamp: None,
+        }
+    }
+
+    fn clock(now: u64) -> Clock {
+        Clock { now, day: 0 }
+    }
+
+    #[test]
+    fn dispute_ratio_should_flag_when_above_threshold() {
+        let mut rules = RuleSet::new(vec![Box::new(DisputeRatio {
+            client: None,
+            threshold: 0.5,
+            action: RuleAction::Flag,
+        })]);
+        let account = Account::new(100);
+        rules.record(&transaction(TransactionType::Deposit, 1, Some(1.0)), clock(1));
+        rules.record(&transaction(TransactionType::Deposit, 2, Some(1.0)), clock(2));
+
+        let dispute = transaction(TransactionType::Dispute, 1, None);
+        assert!(rules.check(&dispute, &account, clock(3)).unwrap().is_empty());
+        rules.record(&dispute, clock(3));
+
+        let dispute = transaction(TransactionType::Dispute, 2, None);
+        assert!(rules.check(&dispute, &account, clock(4)).unwrap().len() == 1);
+    }
+
+    #[test]
+    fn deposit_then_withdrawal_should_block_withdrawal_of_same_amount() {
+        let mut rules = RuleSet::new(vec![Box::new(DepositThenWithdrawal {
+            client: None,
+            action: RuleAction::Reject,
+        })]);
+        let account = Account::new(100);
+        rules.record(&transaction(TransactionType::Deposit, 1, Some(50.0)), clock(1));
+
+        let withdrawal = transaction(TransactionType::Withdrawal, 2, Some(20.0));
+        assert!(rules.check(&withdrawal, &account, clock(2)).is_ok());
+
+        let withdrawal = transaction(TransactionType::Withdrawal, 2, Some(50.0));
+        assert!(rules.check(&withdrawal, &account, clock(2)).is_err());
+    }
+
+    #[test]
+    fn dispute_burst_should_flag_many_disputes_in_window() {
+        let mut rules = RuleSet::new(vec![Box::new(DisputeBurst {
+            client: None,
+            count: 1,
+            window: 5,
+            action: RuleAction::Flag,
+        })]);
+        let account = Account::new(100);
+        let dispute = transaction(TransactionType::Dispute, 1, None);
+        rules.record(&dispute, clock(1));
+
+        assert!(rules.check(&dispute, &account, clock(3)).unwrap().len() == 1);
+        assert!(rules.check(&dispute, &account, clock(10)).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+use flate2::read::MultiGzDecoder;
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+// the input name that stands for stdin
+pub const STDIN: &str = "-";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+// the order input files are processed in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileOrder {
+    Lexical,
+    // oldest modification time first
+    Modified,
+    // by the last number in the file name, such as the hour in `partner-2023111408.csv`
+    Sequence,
+}
+
+impl FileOrder {
+    pub fn parse(item: &str) -> Result<FileOrder, String> {
+        match item {
+            "lexical" => Ok(FileOrder::Lexical),
+            "mtime" => Ok(FileOrder::Modified),
+            "sequence" => Ok(FileOrder::Sequence),
+            _ => Err("file order must be lexical, mtime or sequence".into()),
+        }
+    }
+}
+
+// what happened to the rows of one input file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileStats {
+    pub path: PathBuf,
+    pub rows: u64,
+    pub processed: u64,
+    pub rejected: u64,
+}
+
+impl FileStats {
+    pub fn new(path: PathBuf) -> Self {
+        FileStats {
+            path,
+            ..FileStats::default()
+        }
+    }
+}
+
+// a row that was not processed, and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reject {
+    // index of the file in the list of inputs
+    pub file: usize,
+    pub line: u64,
+    pub reason: String,
+}
+
+fn is_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+// the inputs a directory is expanded to, anything else in it is left alone
+fn is_input(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| [".csv", ".csv.gz", ".csv.zst"].iter().any(|extension| name.ends_with(extension)))
+}
+
+fn directory_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => return Err(format!("unable to read {}: {}", dir.display(), err)),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(err) => return Err(format!("unable to read {}: {}", dir.display(), err)),
+        };
+        if path.is_file() && !is_hidden(&path) && is_input(&path) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn pattern_files(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let paths = match glob::glob(pattern) {
+        Ok(paths) => paths,
+        Err(err) => return Err(format!("invalid pattern {}: {}", pattern, err)),
+    };
+    // glob yields paths in lexical order
+    let files: Vec<PathBuf> = paths.flatten().filter(|path| path.is_file()).collect();
+    if files.is_empty() {
+        return Err(format!("no files match {}", pattern));
+    }
+    Ok(files)
+}
+
+// the last run of digits in the file name
+pub fn sequence_number(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    let end = stem.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = stem[..end].trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    stem[start..end].parse().ok()
+}
+
+fn sort_files(files: &mut [PathBuf], order: FileOrder) -> Result<(), String> {
+    match order {
+        FileOrder::Lexical => files.sort(),
+        FileOrder::Sequence => {
+            // files without a number go last
+            files.sort_by_cached_key(|path| {
+                let sequence = sequence_number(path);
+                (sequence.is_none(), sequence, path.clone())
+            })
+        }
+        FileOrder::Modified => {
+            let mut modified = Vec::with_capacity(files.len());
+            for path in files.iter() {
+                if path == Path::new(STDIN) {
+                    return Err("stdin has no modification time".into());
+                }
+                match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+                    Ok(time) => modified.push((time, path.clone())),
+                    Err(err) => return Err(format!("unable to read {}: {}", path.display(), err)),
+                }
+            }
+            modified.sort();
+            for (file, (_, path)) in files.iter_mut().zip(modified) {
+                *file = path;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().is_some_and(|ext| ext == extension)
+}
+
+// decompresses gzip and zstd input, found by the file extension or by its first bytes
+fn decode<R: BufRead + 'static>(mut reader: R, path: &Path) -> io::Result<Box<dyn Read>> {
+    let start = reader.fill_buf()?;
+    if has_extension(path, "gz") || start.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else if has_extension(path, "zst") || start.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(zstd::stream::read::Decoder::with_buffer(reader)?))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+// opens an input file, or stdin for `-`
+pub fn open_input(path: &Path) -> Result<Box<dyn Read>, String> {
+    let opened = if path == Path::new(STDIN) {
+        decode(BufReader::new(io::stdin()), path)
+    } else {
+        File::open(path).and_then(|file| decode(BufReader::new(file), path))
+    };
+    match opened {
+        Ok(reader) => Ok(reader),
+        Err(err) => Err(format!("unable to read {}: {}", path.display(), err)),
+    }
+}
+
+/*
+    Expands paths, globs and directories into the list of files to process.
+    Without an order, files are taken in the order they are given, with the
+    contents of each glob or directory in lexical order. A file given twice
+    is only processed once.
+*/
+pub fn expand_inputs(inputs: &[&str], order: Option<FileOrder>) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            files.extend(directory_files(path)?);
+        } else if !path.exists() && is_pattern(input) {
+            files.extend(pattern_files(input)?);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    let mut seen = HashSet::new();
+    files.retain(|path| seen.insert(path.clone()));
+
+    if let Some(order) = order {
+        sort_files(&mut files, order)?;
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, expand_inputs, sequence_number, FileOrder};
+    use flate2::{write::GzEncoder, Compression};
+    use std::{
+        fs,
+        io::{BufReader, Read, Write},
+        path::Path,
+        path::PathBuf,
+    };
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust_tx_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn should_find_the_last_number_in_the_file_name() {
+        assert!(sequence_number(Path::new("in/partner7-2023111408.csv")) == Some(2023111408));
+        assert!(sequence_number(Path::new("batch_12_final.csv")) == Some(12));
+        assert!(sequence_number(Path::new("partner.csv")).is_none());
+        assert!(sequence_number(Path::new("é7.csv")) == Some(7));
+    }
+
+    #[test]
+    fn should_expand_directories_and_globs() {
+        let dir = scratch_dir("expand");
+        for name in ["hour-10.csv", "hour-9.csv", "hour-8.csv.gz", "hour-7.csv.zst", ".hidden.csv", "notes.txt", "hour-6.gz"] {
+            fs::write(dir.join(name), "type, client, tx, amount\n").unwrap();
+        }
+        let dir_str = dir.to_str().unwrap();
+
+        let files = expand_inputs(&[dir_str], None).unwrap();
+        let expected = ["hour-10.csv", "hour-7.csv.zst", "hour-8.csv.gz", "hour-9.csv"].map(|name| dir.join(name));
+        assert!(files == expected);
+
+        let pattern = format!("{}/hour-*.csv", dir_str);
+        let files = expand_inputs(&[&pattern], Some(FileOrder::Sequence)).unwrap();
+        assert!(files == vec![dir.join("hour-9.csv"), dir.join("hour-10.csv")]);
+
+        let missing = format!("{}/*.json", dir_str);
+        assert!(expand_inputs(&[&missing], None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn read_all(bytes: Vec<u8>, name: &str) -> String {
+        let mut decoded = String::new();
+        decode(BufReader::new(std::io::Cursor::new(bytes)), Path::new(name))
+            .unwrap()
+            .read_to_string(&mut decoded)
+            .unwrap();
+        decoded
+    }
+
+    #[test]
+    fn should_decompress_by_extension_or_magic_bytes() {
+        let csv = "type, client, tx, amount\ndeposit, 1, 1, 1.0\n";
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(csv.as_bytes()).unwrap();
+        let gzip = encoder.finish().unwrap();
+        let zstd = zstd::encode_all(csv.as_bytes(), 0).unwrap();
+
+        assert!(read_all(gzip.clone(), "hour.gz") == csv);
+        assert!(read_all(gzip, "-") == csv);
+        assert!(read_all(zstd, "hour.csv") == csv);
+        assert!(read_all(csv.as_bytes().to_vec(), "hour.csv") == csv);
+    }
+
+    #[test]
+    fn should_report_corrupt_archives() {
+        for bytes in [vec![0x1f, 0x8b, 0x08, 0xff, 0x00], vec![0x28, 0xb5, 0x2f, 0xfd, 0xff, 0xff, 0xff]] {
+            let mut decoded = String::new();
+            let read = decode(BufReader::new(std::io::Cursor::new(bytes)), Path::new("-"))
+                .and_then(|mut reader| reader.read_to_string(&mut decoded));
+            assert!(read.is_err());
+        }
+    }
+
+    #[test]
+    fn should_keep_the_given_order_and_drop_duplicates() {
+        let files = expand_inputs(&["b.csv", "a.csv", "b.csv"], None).unwrap();
+        assert!(files == vec![PathBuf::from("b.csv"), PathBuf::from("a.csv")]);
+
+        let files = expand_inputs(&["b.csv", "a.csv"], Some(FileOrder::Lexical)).unwrap();
+        assert!(files == vec![PathBuf::from("a.csv"), PathBuf::from("b.csv")]);
+        assert!(FileOrder::parse("newest").is_err());
+    }
+}
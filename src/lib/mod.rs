@@ -1,3 +1,13 @@
 pub mod transaction;
 pub mod account;
-pub mod transaction_processor;
\ No newline at end of file
+pub mod transaction_processor;
+pub mod rules;
+pub mod fraud;
+pub mod reorder;
+pub mod clients;
+pub mod input;
+pub mod diff;
+pub mod synthetic;
+pub mod model;
+pub mod reconcile;
+pub mod error;
@@ -0,0 +1,199 @@
+/*
+    A reference model of deposits, withdrawals, disputes, resolves and
+    chargebacks, kept as simple as the rules allow. Money is counted in
+    ten-thousandths, the precision of the input, so the model itself can't
+    drift. The property tests below check TransactionProcessor against it on
+    random transaction streams, and `gen` uses it for the expected accounts.
+*/
+use crate::transaction::{to_units, ClientId, Transaction, TransactionType, TxId};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ModelAccount {
+    // in ten-thousandths
+    pub available: i64,
+    pub held: i64,
+    pub locked: bool,
+}
+
+impl ModelAccount {
+    pub fn total(&self) -> i64 {
+        self.available + self.held
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Settled,
+    Disputed,
+    ChargedBack,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Stored {
+    client: ClientId,
+    units: i64,
+    state: State,
+}
+
+// accounts open on their first change, any deposit or withdrawal can be disputed, even a refused one
+#[derive(Debug, Default)]
+pub struct Model {
+    accounts: BTreeMap<ClientId, ModelAccount>,
+    transactions: HashMap<TxId, Stored>,
+}
+
+impl Model {
+    pub fn new() -> Self {
+        Model::default()
+    }
+
+    // other transaction types, and invalid transactions, change nothing
+    pub fn apply(&mut self, transaction: &Transaction) {
+        if !transaction.is_valid() {
+            return;
+        }
+        let client = transaction.client;
+        let units = transaction.amount.map_or(0, to_units);
+        if matches!(transaction.transaction_type, TransactionType::Deposit | TransactionType::Withdrawal) {
+            self.transactions.insert(
+                transaction.tx,
+                Stored {
+                    client,
+                    units,
+                    state: State::Settled,
+                },
+            );
+        }
+        let mut account = self.accounts.get(&client).copied().unwrap_or_default();
+        if account.locked {
+            return;
+        }
+        match transaction.transaction_type {
+            TransactionType::Deposit => account.available += units,
+            TransactionType::Withdrawal if account.available >= units => account.available -= units,
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                let stored = match self.transactions.get_mut(&transaction.tx) {
+                    Some(stored) if stored.client == client => stored,
+                    _ => return,
+                };
+                match (transaction.transaction_type, stored.state) {
+                    (TransactionType::Dispute, State::Settled) => {
+                        account.available -= stored.units;
+                        account.held += stored.units;
+                        stored.state = State::Disputed;
+                    }
+                    (TransactionType::Resolve, State::Disputed) => {
+                        account.available += stored.units;
+                        account.held -= stored.units;
+                        stored.state = State::Settled;
+                    }
+                    (TransactionType::Chargeback, State::Disputed) => {
+                        account.held -= stored.units;
+                        account.locked = true;
+                        stored.state = State::ChargedBack;
+                    }
+                    _ => return,
+                }
+            }
+            _ => return,
+        }
+        self.accounts.insert(client, account);
+    }
+
+    pub fn get_account(&self, client: &ClientId) -> Option<&ModelAccount> {
+        self.accounts.get(client)
+    }
+
+    // by client id
+    pub fn accounts(&self) -> impl Iterator<Item = (&ClientId, &ModelAccount)> {
+        self.accounts.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Model;
+    use crate::{
+        transaction::{to_units, ClientId, Transaction, TransactionType, TxId},
+        transaction_processor::TransactionProcessor,
+    };
+    use proptest::prelude::*;
+
+    fn assert_matches(processor: &TransactionProcessor, model: &Model, clients: ClientId) -> Result<(), TestCaseError> {
+        for client in 0..clients {
+            match (processor.get_account(&client), model.get_account(&client)) {
+                (None, None) => {}
+                (Some(account), Some(expected)) => {
+                    prop_assert_eq!(to_units(account.available), expected.available, "available of client {}", client);
+                    prop_assert_eq!(to_units(account.held), expected.held, "held of client {}", client);
+                    prop_assert_eq!(to_units(account.total), expected.total(), "total of client {}", client);
+                    prop_assert_eq!(account.locked, expected.locked, "locked of client {}", client);
+                }
+                (account, expected) => prop_assert!(false, "client {}: processor {:?}, model {:?}", client, account, expected),
+            }
+        }
+        Ok(())
+    }
+
+    const CLIENTS: ClientId = 4;
+
+    fn transaction(transaction_type: TransactionType, client: ClientId, tx: TxId, cents: Option<i64>) -> Transaction {
+        Transaction {
+            transaction_type,
+            client,
+            tx,
+            amount: cents.map(|cents| cents as f64 / 100.0),
+            disputed: false,
+            timestamp: None,
+        }
+    }
+
+    // deposits and withdrawals get their position as a unique tx id, the rest refer to any position
+    fn transactions() -> impl Strategy<Value = Vec<Transaction>> {
+        prop::collection::vec((0..5u8, 0..CLIENTS, 0..48u64, 1..100_000i64), 0..48).prop_map(|rows| {
+            rows.into_iter()
+                .enumerate()
+                .map(|(index, (kind, client, tx, cents))| match kind {
+                    0 => transaction(TransactionType::Deposit, client, index as TxId, Some(cents)),
+                    1 => transaction(TransactionType::Withdrawal, client, index as TxId, Some(cents)),
+                    2 => transaction(TransactionType::Dispute, client, tx, None),
+                    3 => transaction(TransactionType::Resolve, client, tx, None),
+                    _ => transaction(TransactionType::Chargeback, client, tx, None),
+                })
+                .collect()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn processor_should_match_the_reference_model(transactions in transactions()) {
+            let mut processor = TransactionProcessor::new();
+            let mut model = Model::new();
+            for transaction in transactions {
+                // rejected transactions leave the accounts as they were
+                let _ = processor.process_transaction(transaction);
+                model.apply(&transaction);
+                assert_matches(&processor, &model, CLIENTS)?;
+            }
+        }
+
+        #[test]
+        fn clients_should_not_affect_each_other(transactions in transactions()) {
+            let mut interleaved = TransactionProcessor::new();
+            for transaction in transactions.iter() {
+                let _ = interleaved.process_transaction(*transaction);
+            }
+            for client in 0..CLIENTS {
+                let mut alone = TransactionProcessor::new();
+                for transaction in transactions.iter().filter(|transaction| transaction.client == client) {
+                    let _ = alone.process_transaction(*transaction);
+                }
+                let balances = |processor: &TransactionProcessor| {
+                    processor.get_account(&client).map(|account| (to_units(account.available), to_units(account.held), account.locked))
+                };
+                prop_assert_eq!(balances(&interleaved), balances(&alone));
+            }
+        }
+    }
+}
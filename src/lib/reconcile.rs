@@ -0,0 +1,274 @@
+/*
+    Compares the accounts at the end of a run with the closing balances
+    finance expects, given in the `client, available, held, total, locked`
+    shape that process writes. Amounts are compared in ten-thousandths, the
+    precision they are written with, and match when they are at most
+    `tolerance` apart. Locked has to be the same.
+*/
+use super::{
+    account::Account,
+    diff::decimal,
+    transaction::{parse_id, to_units, ClientId},
+};
+use csv::{StringRecord, Trim};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Balance {
+    pub client: ClientId,
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub locked: bool,
+}
+
+impl From<&Account> for Balance {
+    fn from(account: &Account) -> Self {
+        Balance {
+            client: account.client,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Issue {
+    // a balance differs by more than the tolerance
+    Mismatch,
+    // expected, but there is no account for the client
+    Missing,
+    // an account for a client that isn't expected
+    Unexpected,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Issue::Mismatch => "mismatch",
+            Issue::Missing => "missing",
+            Issue::Unexpected => "unexpected",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// a client that doesn't reconcile, a missing or unexpected client is reported by its total
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discrepancy {
+    pub client: ClientId,
+    pub issue: Issue,
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+fn parse_amount(record: &StringRecord, index: usize, name: &str) -> Result<f64, String> {
+    match record.get(index).map(|value| value.parse::<f64>()) {
+        Some(Ok(value)) if value.is_finite() => Ok(value),
+        _ => Err(format!("unable to parse {}", name)),
+    }
+}
+
+impl Balance {
+    fn parse(record: &StringRecord, columns: &[usize; 5]) -> Result<Balance, String> {
+        let locked = match record.get(columns[4]) {
+            Some("true") => true,
+            Some("false") => false,
+            _ => return Err("unable to parse locked".into()),
+        };
+        Ok(Balance {
+            client: parse_id(record.get(columns[0]).unwrap_or_default(), "client")?,
+            available: parse_amount(record, columns[1], "available")?,
+            held: parse_amount(record, columns[2], "held")?,
+            total: parse_amount(record, columns[3], "total")?,
+            locked,
+        })
+    }
+}
+
+const COLUMNS: [&str; 5] = ["client", "available", "held", "total", "locked"];
+
+// every column is needed, in any order, and a client can only be given once
+pub fn load_balances<P: AsRef<Path>>(path: P) -> Result<Vec<Balance>, String> {
+    let mut reader = match csv::ReaderBuilder::new().flexible(true).trim(Trim::All).from_path(path) {
+        Ok(reader) => reader,
+        Err(err) => return Err(format!("unable to read expected balances: {}", err)),
+    };
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(err) => return Err(format!("unable to read expected balances: {}", err)),
+    };
+    let mut columns = [0; 5];
+    for (column, name) in columns.iter_mut().zip(COLUMNS) {
+        *column = match headers.iter().position(|header| header == name) {
+            Some(index) => index,
+            None => return Err(format!("expected balances have no {} column", name)),
+        };
+    }
+
+    let mut balances: Vec<Balance> = Vec::new();
+    let mut seen = BTreeMap::new();
+    for (line, result) in reader.records().enumerate() {
+        let record = match result {
+            Ok(record) => record,
+            Err(err) => return Err(format!("unable to read expected balances: {}", err)),
+        };
+        // the header is line 1
+        let balance = Balance::parse(&record, &columns).map_err(|err| format!("line {}: {}", line + 2, err))?;
+        if let Some(first) = seen.insert(balance.client, line + 2) {
+            return Err(format!("line {}: client {} is already on line {}", line + 2, balance.client, first));
+        }
+        balances.push(balance);
+    }
+    Ok(balances)
+}
+
+// by client, then by field
+pub fn reconcile(expected: &[Balance], actual: &[Balance], tolerance: f64) -> Vec<Discrepancy> {
+    let mut clients: BTreeMap<ClientId, (Option<&Balance>, Option<&Balance>)> = BTreeMap::new();
+    for balance in expected {
+        clients.entry(balance.client).or_default().0 = Some(balance);
+    }
+    for balance in actual {
+        clients.entry(balance.client).or_default().1 = Some(balance);
+    }
+    let tolerance = to_units(tolerance);
+
+    let mut discrepancies = Vec::new();
+    for (client, sides) in clients {
+        match sides {
+            (Some(expected), Some(actual)) => {
+                let amounts = [
+                    ("available", expected.available, actual.available),
+                    ("held", expected.held, actual.held),
+                    ("total", expected.total, actual.total),
+                ];
+                for (field, expected, actual) in amounts {
+                    if (to_units(expected) - to_units(actual)).abs() > tolerance {
+                        discrepancies.push(Discrepancy {
+                            client,
+                            issue: Issue::Mismatch,
+                            field,
+                            expected: decimal(expected),
+                            actual: decimal(actual),
+                        });
+                    }
+                }
+                if expected.locked != actual.locked {
+                    discrepancies.push(Discrepancy {
+                        client,
+                        issue: Issue::Mismatch,
+                        field: "locked",
+                        expected: expected.locked.to_string(),
+                        actual: actual.locked.to_string(),
+                    });
+                }
+            }
+            (Some(expected), None) => discrepancies.push(Discrepancy {
+                client,
+                issue: Issue::Missing,
+                field: "total",
+                expected: decimal(expected.total),
+                actual: String::new(),
+            }),
+            (None, Some(actual)) => discrepancies.push(Discrepancy {
+                client,
+                issue: Issue::Unexpected,
+                field: "total",
+                expected: String::new(),
+                actual: decimal(actual.total),
+            }),
+            (None, None) => {}
+        }
+    }
+    discrepancies
+}
+
+// a client can mismatch on several fields, it is counted once
+pub fn mismatched_clients(discrepancies: &[Discrepancy]) -> BTreeSet<ClientId> {
+    discrepancies
+        .iter()
+        .filter(|discrepancy| discrepancy.issue == Issue::Mismatch)
+        .map(|discrepancy| discrepancy.client)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_balances, mismatched_clients, reconcile, Balance, Discrepancy, Issue};
+    use std::fs;
+
+    fn balance(client: u64, available: f64, held: f64, locked: bool) -> Balance {
+        Balance {
+            client,
+            available,
+            held,
+            total: available + held,
+            locked,
+        }
+    }
+
+    #[test]
+    fn reconcile_should_allow_differences_within_the_tolerance() {
+        let expected = [balance(1, 10.0, 2.0, false)];
+        assert!(reconcile(&expected, &[balance(1, 10.00004, 2.0, false)], 0.0).is_empty());
+        assert!(reconcile(&expected, &[balance(1, 10.01, 2.0, false)], 0.01).is_empty());
+
+        let discrepancies = reconcile(&expected, &[balance(1, 10.02, 2.0, true)], 0.01);
+        let fields: Vec<&str> = discrepancies.iter().map(|discrepancy| discrepancy.field).collect();
+        assert!(fields == vec!["available", "total", "locked"]);
+        assert!(discrepancies.iter().all(|discrepancy| discrepancy.issue == Issue::Mismatch));
+        assert!(discrepancies[0].expected == "10.0000" && discrepancies[0].actual == "10.0200");
+    }
+
+    #[test]
+    fn reconcile_should_report_missing_and_unexpected_clients() {
+        let expected = [balance(1, 1.0, 0.0, false), balance(2, 5.0, 0.0, false)];
+        let actual = [balance(3, 7.5, 0.0, false), balance(1, 1.0, 0.0, false)];
+        let discrepancies = reconcile(&expected, &actual, 0.0);
+        assert!(discrepancies.len() == 2);
+        assert!(discrepancies[0].client == 2 && discrepancies[0].issue == Issue::Missing);
+        assert!(discrepancies[0].expected == "5.0000" && discrepancies[0].actual.is_empty());
+        assert!(discrepancies[1].client == 3 && discrepancies[1].issue == Issue::Unexpected);
+        assert!(discrepancies[1].actual == "7.5000");
+    }
+
+    #[test]
+    fn mismatched_clients_should_count_each_client_once() {
+        let mismatch = |client, field| Discrepancy {
+            client,
+            issue: Issue::Mismatch,
+            field,
+            expected: String::new(),
+            actual: String::new(),
+        };
+        let discrepancies = [mismatch(1, "available"), mismatch(2, "held"), mismatch(1, "locked")];
+        assert!(mismatched_clients(&discrepancies).into_iter().collect::<Vec<_>>() == vec![1, 2]);
+
+        let expected = [balance(1, 10.0, 2.0, false), balance(2, 1.0, 0.0, false)];
+        let actual = [balance(1, 11.0, 2.0, true), balance(3, 1.0, 0.0, false)];
+        assert!(mismatched_clients(&reconcile(&expected, &actual, 0.0)).len() == 1);
+    }
+
+    #[test]
+    fn load_balances_should_need_every_column_and_each_client_once() {
+        let path = std::env::temp_dir().join(format!("rust_tx_balances_{}", std::process::id()));
+        fs::write(&path, "locked, total, held, available, client\nfalse, 3.5, 1.0, 2.5, 7\n").unwrap();
+        assert!(load_balances(&path).unwrap() == vec![balance(7, 2.5, 1.0, false)]);
+
+        fs::write(&path, "client, available, held, locked\n7, 2.5, 1.0, false\n").unwrap();
+        assert!(load_balances(&path).unwrap_err().contains("total"));
+        fs::write(&path, "client, available, held, total, locked\n7, 1, 0, 1, false\n7, 2, 0, 2, false\n").unwrap();
+        assert!(load_balances(&path).unwrap_err().starts_with("line 3"));
+        fs::write(&path, "client, available, held, total, locked\n7, 1, 0, 1, no\n").unwrap();
+        assert!(load_balances(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
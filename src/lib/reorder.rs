@@ -0,0 +1,136 @@
+use super::transaction::Transaction;
+use std::{cmp::Ordering, cmp::Reverse, collections::BinaryHeap};
+
+/*
+    Holds transactions back until no row within `window` milliseconds can
+    overtake them, then releases them in event time order. A row without a
+    timestamp is ordered at the latest timestamp pushed so far, so it stays
+    behind every row pushed before it, and rows before the first timestamp
+    are ordered at 0. Its own timestamp is left empty. Each transaction
+    carries a tag, such as where it was read from, that is released along
+    with it.
+*/
+pub struct ReorderBuffer<T = ()> {
+    window: u64,
+    latest: u64,
+    row: u64,
+    pending: BinaryHeap<Reverse<Pending<T>>>,
+}
+
+struct Pending<T> {
+    timestamp: u64,
+    row: u64,
+    transaction: Transaction,
+    tag: T,
+}
+
+impl<T> PartialEq for Pending<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Pending<T> {}
+
+impl<T> PartialOrd for Pending<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Pending<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.timestamp, self.row).cmp(&(other.timestamp, other.row))
+    }
+}
+
+impl<T> ReorderBuffer<T> {
+    pub fn new(window: u64) -> Self {
+        ReorderBuffer {
+            window,
+            latest: 0,
+            row: 0,
+            pending: BinaryHeap::new(),
+        }
+    }
+
+    // returns the transactions that are now ready, oldest first
+    pub fn push(&mut self, transaction: Transaction, tag: T) -> Vec<(Transaction, T)> {
+        let timestamp = transaction.timestamp.unwrap_or(self.latest);
+        self.latest = self.latest.max(timestamp);
+        self.row += 1;
+        self.pending.push(Reverse(Pending {
+            timestamp,
+            row: self.row,
+            transaction,
+            tag,
+        }));
+
+        let mut ready = Vec::new();
+        while let Some(Reverse(oldest)) = self.pending.peek() {
+            if oldest.timestamp.saturating_add(self.window) > self.latest {
+                break;
+            }
+            if let Some(Reverse(oldest)) = self.pending.pop() {
+                ready.push((oldest.transaction, oldest.tag));
+            }
+        }
+        ready
+    }
+
+    // releases everything that is still held back, at the end of the input
+    pub fn drain(&mut self) -> Vec<(Transaction, T)> {
+        let mut ready = Vec::new();
+        while let Some(Reverse(oldest)) = self.pending.pop() {
+            ready.push((oldest.transaction, oldest.tag));
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReorderBuffer;
+    use crate::transaction::{Transaction, TransactionType, TxId};
+
+    fn transaction(tx: TxId, timestamp: Option<u64>) -> Transaction {
+        Transaction {
+            transaction_type: TransactionType::Deposit,
+            client: 100,
+            tx,
+            amount: Some(1.0),
+            disputed: false,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn should_release_transactions_in_event_time_order() {
+        let mut buffer = ReorderBuffer::new(10);
+        assert!(buffer.push(transaction(1, Some(5)), ()).is_empty());
+        assert!(buffer.push(transaction(2, Some(3)), ()).is_empty());
+        assert!(buffer.push(transaction(3, None), ()).is_empty());
+
+        let ready: Vec<TxId> = buffer.push(transaction(4, Some(14)), ()).iter().map(|(t, _)| t.tx).collect();
+        assert!(ready == vec![2]);
+
+        let ready: Vec<TxId> = buffer.drain().iter().map(|(t, _)| t.tx).collect();
+        assert!(ready == vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn zero_window_should_not_hold_anything_back() {
+        let mut buffer = ReorderBuffer::new(0);
+        assert!(buffer.push(transaction(1, Some(5)), ()).len() == 1);
+        assert!(buffer.push(transaction(2, Some(3)), ()).len() == 1);
+    }
+
+    #[test]
+    fn should_release_each_tag_with_its_transaction() {
+        let mut buffer = ReorderBuffer::new(10);
+        buffer.push(transaction(1, Some(5)), "a.csv");
+        buffer.push(transaction(2, Some(3)), "b.csv");
+        let ready: Vec<(TxId, &str)> = buffer.drain().iter().map(|(t, tag)| (t.tx, *tag)).collect();
+        assert!(ready == vec![(2, "b.csv"), (1, "a.csv")]);
+    }
+}
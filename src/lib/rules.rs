@@ -0,0 +1,465 @@
+use super::{
+    account::Account,
+    fraud::{DepositThenWithdrawal, DisputeBurst, DisputeRatio},
+    transaction::{parse_id, ClientId, Transaction, TransactionType, TxId},
+};
+use csv::{StringRecord, Trim};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+};
+
+/*
+    Rules are evaluated by the TransactionProcessor before an Account is touched.
+    Once rows carry a timestamp the clock is event time, and windows are measured
+    in milliseconds. Without timestamps the clock is the row number: windows are
+    measured in rows and the whole input counts as a single business day.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Clock {
+    pub now: u64,
+    pub day: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Allow,
+    // the transaction is applied, but an alert is raised
+    Flag(String),
+    // the transaction is not applied to the account
+    Block(String),
+}
+
+pub trait Rule {
+    fn name(&self) -> &'static str;
+
+    fn evaluate(&self, tx: &Transaction, account: &Account, history: &ClientHistory, clock: Clock) -> Verdict;
+
+    // how many ticks of the clock the rule needs to look back through the history
+    fn window(&self) -> u64 {
+        0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuleAction {
+    Reject,
+    Flag,
+}
+
+impl RuleAction {
+    fn parse(item: &str) -> Result<RuleAction, String> {
+        match item {
+            "reject" => Ok(RuleAction::Reject),
+            "flag" => Ok(RuleAction::Flag),
+            _ => Err("Not a valid rule action".into()),
+        }
+    }
+
+    pub fn verdict(self, reason: String) -> Verdict {
+        match self {
+            RuleAction::Reject => Verdict::Block(reason),
+            RuleAction::Flag => Verdict::Flag(reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    // largest amount a single deposit or withdrawal may carry
+    MaxAmount(f64),
+    // largest total a client may withdraw in a day
+    MaxDailyWithdrawal(f64),
+    // most deposits and withdrawals a client may make within `window` ticks of the clock
+    MaxTransactions { count: usize, window: u64 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LimitRule {
+    // None applies the rule to every client
+    pub client: Option<ClientId>,
+    pub limit: Limit,
+    pub action: RuleAction,
+}
+
+impl Rule for LimitRule {
+    fn name(&self) -> &'static str {
+        match self.limit {
+            Limit::MaxAmount(_) => "max_amount",
+            Limit::MaxDailyWithdrawal(_) => "daily_withdrawal",
+            Limit::MaxTransactions { .. } => "velocity",
+        }
+    }
+
+    fn evaluate(&self, tx: &Transaction, _account: &Account, history: &ClientHistory, clock: Clock) -> Verdict {
+        if !applies_to(self.client, tx.client) || !moves_funds(&tx.transaction_type) {
+            return Verdict::Allow;
+        }
+        let amount = tx.amount.unwrap_or(0.0);
+
+        let reason = match self.limit {
+            Limit::MaxAmount(max) if amount > max => {
+                Some(format!("amount {} is above the limit of {}", amount, max))
+            }
+            Limit::MaxDailyWithdrawal(max)
+                if matches!(tx.transaction_type, TransactionType::Withdrawal) =>
+            {
+                let withdrawn = history.withdrawn_on(clock.day) + amount;
+                if withdrawn > max {
+                    Some(format!("daily withdrawals of {} are above the limit of {}", withdrawn, max))
+                } else {
+                    None
+                }
+            }
+            Limit::MaxTransactions { count, window } => {
+                let seen = history.count_since(clock.now.saturating_sub(window), moves_funds);
+                if seen + 1 > count {
+                    Some(format!("more than {} transactions within {}", count, window))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        match reason {
+            Some(reason) => self.action.verdict(reason),
+            None => Verdict::Allow,
+        }
+    }
+
+    fn window(&self) -> u64 {
+        match self.limit {
+            Limit::MaxTransactions { window, .. } => window,
+            _ => 0,
+        }
+    }
+}
+
+pub fn applies_to(rule_client: Option<ClientId>, client: ClientId) -> bool {
+    match rule_client {
+        Some(id) => id == client,
+        None => true,
+    }
+}
+
+fn moves_funds(transaction_type: &TransactionType) -> bool {
+    matches!(
+        transaction_type,
+        TransactionType::Deposit | TransactionType::Withdrawal
+    )
+}
+
+// client, rule, limit, window, action
+pub fn parse_rule(record: &StringRecord) -> Result<Box<dyn Rule>, String> {
+    if record.len() < 5 {
+        return Err("unable to parse rule".into());
+    }
+
+    let client = match &record[0] {
+        "*" => None,
+        id => Some(parse_id(id, "client")?),
+    };
+    let action = RuleAction::parse(&record[4])?;
+
+    let rule: Box<dyn Rule> = match &record[1] {
+        "max_amount" => Box::new(LimitRule {
+            client,
+            limit: Limit::MaxAmount(parse_limit(&record[2])?),
+            action,
+        }),
+        "daily_withdrawal" => Box::new(LimitRule {
+            client,
+            limit: Limit::MaxDailyWithdrawal(parse_limit(&record[2])?),
+            action,
+        }),
+        "velocity" => Box::new(LimitRule {
+            client,
+            limit: Limit::MaxTransactions {
+                count: parse_count(&record[2])?,
+                window: parse_window(&record[3])?,
+            },
+            action,
+        }),
+        "dispute_ratio" => Box::new(DisputeRatio {
+            client,
+            threshold: parse_limit(&record[2])?,
+            action,
+        }),
+        "deposit_withdrawal" => Box::new(DepositThenWithdrawal { client, action }),
+        "dispute_burst" => Box::new(DisputeBurst {
+            client,
+            count: parse_count(&record[2])?,
+            window: parse_window(&record[3])?,
+            action,
+        }),
+        _ => return Err("Not a valid rule".into()),
+    };
+    Ok(rule)
+}
+
+fn parse_limit(limit_str: &str) -> Result<f64, String> {
+    match limit_str.parse::<f64>() {
+        Ok(limit) if limit >= 0.0 => Ok(limit),
+        _ => Err("unable to parse limit".into()),
+    }
+}
+
+fn parse_count(count_str: &str) -> Result<usize, String> {
+    match count_str.parse::<usize>() {
+        Ok(count) => Ok(count),
+        Err(_) => Err("unable to parse limit".into()),
+    }
+}
+
+fn parse_window(window_str: &str) -> Result<u64, String> {
+    match window_str.parse::<u64>() {
+        Ok(window) if window > 0 => Ok(window),
+        _ => Err("unable to parse window".into()),
+    }
+}
+
+// raised for transactions that a rule flags
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub client: ClientId,
+    pub tx: TxId,
+    pub rule: &'static str,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub transaction: Transaction,
+    pub time: u64,
+}
+
+// what a client has done so far, as seen by the rules
+#[derive(Debug, Default)]
+pub struct ClientHistory {
+    entries: VecDeque<Entry>,
+    pub deposits: usize,
+    pub disputes: usize,
+    day: u64,
+    withdrawn: f64,
+}
+
+impl ClientHistory {
+    pub fn last(&self) -> Option<&Entry> {
+        self.entries.back()
+    }
+
+    pub fn withdrawn_on(&self, day: u64) -> f64 {
+        if self.day == day {
+            self.withdrawn
+        } else {
+            0.0
+        }
+    }
+
+    // number of transactions of the given types after `since`
+    pub fn count_since(&self, since: u64, kind: fn(&TransactionType) -> bool) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.time > since && kind(&entry.transaction.transaction_type))
+            .count()
+    }
+
+    fn record(&mut self, tx: &Transaction, clock: Clock, retention: u64) {
+        self.entries.push_back(Entry {
+            transaction: *tx,
+            time: clock.now,
+        });
+        // the last entry is always kept
+        while self.entries.len() > 1 {
+            match self.entries.front() {
+                Some(oldest) if oldest.time <= clock.now.saturating_sub(retention) => {
+                    self.entries.pop_front();
+                }
+                _ => break,
+            }
+        }
+
+        match tx.transaction_type {
+            TransactionType::Deposit => self.deposits += 1,
+            TransactionType::Dispute => self.disputes += 1,
+            TransactionType::Withdrawal => {
+                if self.day != clock.day {
+                    self.day = clock.day;
+                    self.withdrawn = 0.0;
+                }
+                self.withdrawn += tx.amount.unwrap_or(0.0);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RuleSet {
+    rules: Vec<Box<dyn Rule>>,
+    history: HashMap<ClientId, ClientHistory>,
+}
+
+impl RuleSet {
+    pub fn new(rules: Vec<Box<dyn Rule>>) -> Self {
+        RuleSet {
+            rules,
+            history: HashMap::new(),
+        }
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<RuleSet, String> {
+        let mut reader = match csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(Trim::All)
+            .from_path(path)
+        {
+            Ok(reader) => reader,
+            Err(err) => return Err(format!("unable to read rules: {}", err)),
+        };
+
+        let mut rules = Vec::new();
+        for (line, result) in reader.records().enumerate() {
+            let record = match result {
+                Ok(record) => record,
+                Err(err) => return Err(format!("unable to read rules: {}", err)),
+            };
+            match parse_rule(&record) {
+                Ok(rule) => rules.push(rule),
+                // the header is line 1
+                Err(err) => return Err(format!("line {}: {}", line + 2, err)),
+            }
+        }
+        Ok(RuleSet::new(rules))
+    }
+
+    pub fn push(&mut self, rule: Box<dyn Rule>) {
+        self.rules.push(rule);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // runs every rule, returning the block if there is one, otherwise the alerts
+    pub fn check(&self, tx: &Transaction, account: &Account, clock: Clock) -> Result<Vec<Alert>, String> {
+        let empty = ClientHistory::default();
+        let history = self.history.get(&tx.client).unwrap_or(&empty);
+
+        let mut alerts = Vec::new();
+        for rule in self.rules.iter() {
+            match rule.evaluate(tx, account, history, clock) {
+                Verdict::Allow => {}
+                Verdict::Flag(reason) => alerts.push(Alert {
+                    client: tx.client,
+                    tx: tx.tx,
+                    rule: rule.name(),
+                    reason,
+                }),
+                Verdict::Block(reason) => return Err(format!("{}: {}", rule.name(), reason)),
+            }
+        }
+        Ok(alerts)
+    }
+
+    // called once a transaction has been applied to the account
+    pub fn record(&mut self, tx: &Transaction, clock: Clock) {
+        if self.rules.is_empty() {
+            return;
+        }
+        let retention = self.rules.iter().map(|rule| rule.window()).max().unwrap_or(0);
+        self.history
+            .entry(tx.client)
+            .or_default()
+            .record(tx, clock, retention);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_rule, Clock, Limit, LimitRule, RuleAction, RuleSet};
+    use crate::{
+        account::Account,
+        transaction::{Transaction, TransactionType},
+    };
+    use csv::StringRecord;
+
+    fn transaction(transaction_type: TransactionType, amount: f64) -> Transaction {
+        Transaction {
+            transaction_type,
+            client: 100,
+            tx: 1,
+            amount: Some(amount),
+            disputed: false,
+            timestamp: None,
+        }
+    }
+
+    fn clock(now: u64, day: u64) -> Clock {
+        Clock { now, day }
+    }
+
+    #[test]
+    fn should_parse_rule() {
+        let record = StringRecord::from(vec!["*", "velocity", "10", "100", "flag"]);
+        let rule = parse_rule(&record).unwrap();
+        assert!(rule.name() == "velocity");
+        assert!(rule.window() == 100);
+    }
+
+    #[test]
+    fn should_return_err_if_invalid_rule() {
+        let record = StringRecord::from(vec!["100", "does_not_exist", "10", "", "reject"]);
+        assert!(parse_rule(&record).is_err());
+    }
+
+    #[test]
+    fn max_amount_should_only_apply_to_its_client() {
+        let rules = RuleSet::new(vec![Box::new(LimitRule {
+            client: Some(200),
+            limit: Limit::MaxAmount(50.0),
+            action: RuleAction::Reject,
+        })]);
+        let account = Account::new(100);
+        let tx = transaction(TransactionType::Deposit, 100.0);
+        assert!(rules.check(&tx, &account, clock(1, 0)).is_ok());
+
+        let tx = Transaction { client: 200, ..tx };
+        assert!(rules.check(&tx, &account, clock(1, 0)).is_err());
+    }
+
+    #[test]
+    fn daily_withdrawal_should_accumulate_within_a_day() {
+        let mut rules = RuleSet::new(vec![Box::new(LimitRule {
+            client: None,
+            limit: Limit::MaxDailyWithdrawal(100.0),
+            action: RuleAction::Reject,
+        })]);
+        let account = Account::new(100);
+        let tx = transaction(TransactionType::Withdrawal, 60.0);
+        assert!(rules.check(&tx, &account, clock(1, 0)).is_ok());
+        rules.record(&tx, clock(1, 0));
+
+        assert!(rules.check(&tx, &account, clock(2, 0)).is_err());
+        assert!(rules.check(&tx, &account, clock(2, 1)).is_ok());
+    }
+
+    #[test]
+    fn velocity_should_only_count_transactions_inside_the_window() {
+        let mut rules = RuleSet::new(vec![Box::new(LimitRule {
+            client: None,
+            limit: Limit::MaxTransactions { count: 2, window: 10 },
+            action: RuleAction::Flag,
+        })]);
+        let account = Account::new(100);
+        let tx = transaction(TransactionType::Deposit, 1.0);
+        rules.record(&tx, clock(1, 0));
+        rules.record(&tx, clock(2, 0));
+
+        let alerts = rules.check(&tx, &account, clock(3, 0)).unwrap();
+        assert!(alerts.len() == 1);
+        assert!(alerts[0].rule == "velocity");
+        assert!(rules.check(&tx, &account, clock(12, 0)).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+/*
+    Synthetic transaction streams, for benchmarks and load tests. A profile
+    sets how many clients there are and how often rows are withdrawals,
+    disputes or chargebacks; the rest are deposits. Disputes refer to recent
+    deposits of the same client and are later resolved, unless a chargeback
+    settles them first. The same profile and seed always give the same
+    stream.
+*/
+use crate::transaction::{ClientId, Transaction, TransactionType, TxId};
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Profile {
+    pub clients: ClientId,
+    // shares of the rows, between 0 and 1
+    pub withdrawals: f64,
+    // disputes and the resolves that settle them
+    pub disputes: f64,
+    // chargebacks settle open disputes, so they can only be as many as disputes opened
+    pub chargebacks: f64,
+    // deposits and withdrawals with a missing, negative or not a number amount
+    pub invalid: f64,
+    // 0 spreads rows evenly over the clients, higher puts more of them on low client ids
+    pub skew: f64,
+    pub seed: u64,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            clients: 1000,
+            withdrawals: 0.3,
+            disputes: 0.01,
+            chargebacks: 0.0,
+            invalid: 0.0,
+            skew: 0.0,
+            seed: 1,
+        }
+    }
+}
+
+// deposits kept to be disputed, so memory stays flat on long streams
+const RECENT: usize = 4096;
+
+pub struct Stream {
+    profile: Profile,
+    state: u64,
+    tx: TxId,
+    recent: Vec<(ClientId, TxId)>,
+    disputed: Vec<(ClientId, TxId)>,
+}
+
+impl Stream {
+    pub fn new(profile: Profile) -> Stream {
+        Stream {
+            profile,
+            // xorshift never leaves zero
+            state: profile.seed.max(1),
+            tx: 0,
+            recent: Vec::new(),
+            disputed: Vec::new(),
+        }
+    }
+
+    // xorshift, so streams are repeatable without a dependency
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    // uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // an amount with four decimal places, below `max`
+    fn amount(&mut self, max: u64) -> f64 {
+        (1 + self.next_u64() % (max * 10_000)) as f64 / 10_000.0
+    }
+
+    fn transaction(transaction_type: TransactionType, client: ClientId, tx: TxId, amount: Option<f64>) -> Transaction {
+        Transaction {
+            transaction_type,
+            client,
+            tx,
+            amount,
+            disputed: false,
+            timestamp: None,
+        }
+    }
+
+    // client ids start at 1
+    fn client(&mut self) -> ClientId {
+        let clients = self.profile.clients.max(1);
+        if self.profile.skew <= 0.0 {
+            return 1 + self.next_u64() % clients;
+        }
+        // a power of a uniform draw leans towards 0, more so the higher the skew
+        let draw = self.next_f64().powf(1.0 + self.profile.skew);
+        1 + ((draw * clients as f64) as ClientId).min(clients - 1)
+    }
+
+    fn chargeback(&mut self) -> Option<Transaction> {
+        if self.disputed.is_empty() {
+            return None;
+        }
+        let index = (self.next_u64() % self.disputed.len() as u64) as usize;
+        let (client, tx) = self.disputed.swap_remove(index);
+        Some(Stream::transaction(TransactionType::Chargeback, client, tx, None))
+    }
+
+    // spoils the amount of a deposit or withdrawal
+    fn invalidate(&mut self, mut transaction: Transaction) -> Transaction {
+        transaction.amount = match self.next_u64() % 3 {
+            0 => None,
+            1 => transaction.amount.map(|amount| -amount),
+            _ => Some(f64::NAN),
+        };
+        transaction
+    }
+
+    fn dispute_or_resolve(&mut self) -> Option<Transaction> {
+        // settle about as many disputes as are opened
+        if !self.disputed.is_empty() && self.next_u64().is_multiple_of(2) {
+            let index = (self.next_u64() % self.disputed.len() as u64) as usize;
+            let (client, tx) = self.disputed.swap_remove(index);
+            return Some(Stream::transaction(TransactionType::Resolve, client, tx, None));
+        }
+        if self.recent.is_empty() {
+            return None;
+        }
+        let index = (self.next_u64() % self.recent.len() as u64) as usize;
+        let (client, tx) = self.recent.swap_remove(index);
+        self.disputed.push((client, tx));
+        Some(Stream::transaction(TransactionType::Dispute, client, tx, None))
+    }
+}
+
+impl Iterator for Stream {
+    type Item = Transaction;
+
+    fn next(&mut self) -> Option<Transaction> {
+        let Profile { withdrawals, disputes, chargebacks, invalid, .. } = self.profile;
+        // rows that find nothing to dispute or charge back become withdrawals
+        let roll = self.next_f64();
+        if roll < chargebacks {
+            if let Some(transaction) = self.chargeback() {
+                return Some(transaction);
+            }
+        } else if roll < chargebacks + disputes {
+            if let Some(transaction) = self.dispute_or_resolve() {
+                return Some(transaction);
+            }
+        }
+        self.tx += 1;
+        let client = self.client();
+        let transaction = if roll < chargebacks + disputes + withdrawals {
+            let amount = self.amount(500);
+            Stream::transaction(TransactionType::Withdrawal, client, self.tx, Some(amount))
+        } else {
+            let amount = self.amount(1000);
+            Stream::transaction(TransactionType::Deposit, client, self.tx, Some(amount))
+        };
+        if invalid > 0.0 && self.next_f64() < invalid {
+            return Some(self.invalidate(transaction));
+        }
+        if matches!(transaction.transaction_type, TransactionType::Deposit) {
+            if self.recent.len() == RECENT {
+                let index = (self.next_u64() % RECENT as u64) as usize;
+                self.recent[index] = (client, self.tx);
+            } else {
+                self.recent.push((client, self.tx));
+            }
+        }
+        Some(transaction)
+    }
+}
+
+// writes transactions in the input format, with a header
+pub fn write_csv<W: Write, I: IntoIterator<Item = Transaction>>(mut out: W, transactions: I) -> io::Result<()> {
+    writeln!(out, "type, client, tx, amount")?;
+    for transaction in transactions {
+        match transaction.amount {
+            Some(amount) => writeln!(out, "{}, {}, {}, {:.4}", transaction.transaction_type, transaction.client, transaction.tx, amount)?,
+            None => writeln!(out, "{}, {}, {},", transaction.transaction_type, transaction.client, transaction.tx)?,
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_csv, Profile, Stream};
+    use crate::{
+        model::Model,
+        transaction::{to_units, ClientId, Transaction, TransactionType},
+        transaction_processor::TransactionProcessor,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn stream_should_be_repeatable() {
+        let profile = Profile { seed: 7, ..Profile::default() };
+        let first: Vec<String> = Stream::new(profile).take(1000).map(|tx| format!("{:?}", tx)).collect();
+        let second: Vec<String> = Stream::new(profile).take(1000).map(|tx| format!("{:?}", tx)).collect();
+        assert!(first == second);
+        let other: Vec<String> = Stream::new(Profile { seed: 8, ..profile }).take(1000).map(|tx| format!("{:?}", tx)).collect();
+        assert!(first != other);
+    }
+
+    #[test]
+    fn stream_should_follow_the_profile() {
+        let profile = Profile { clients: 10, withdrawals: 0.2, disputes: 0.1, seed: 3, ..Profile::default() };
+        let mut deposits = HashMap::new();
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for transaction in Stream::new(profile).take(10_000) {
+            assert!((1..=10).contains(&transaction.client));
+            assert!(transaction.is_valid());
+            match transaction.transaction_type {
+                TransactionType::Deposit => {
+                    deposits.insert(transaction.tx, transaction.client);
+                }
+                // disputes and resolves refer to a deposit of the same client
+                TransactionType::Dispute | TransactionType::Resolve => {
+                    assert!(deposits.get(&transaction.tx) == Some(&transaction.client));
+                }
+                _ => {}
+            }
+            *counts.entry(transaction.transaction_type.to_string()).or_default() += 1;
+        }
+        let share = |name: &str| counts.get(name).copied().unwrap_or(0) as f64 / 10_000.0;
+        assert!((share("withdrawal") - 0.2).abs() < 0.02);
+        assert!((share("dispute") + share("resolve") - 0.1).abs() < 0.02);
+    }
+
+    #[test]
+    fn stream_should_skew_clients_and_spoil_amounts() {
+        let profile = Profile { clients: 100, disputes: 0.1, chargebacks: 0.01, invalid: 0.05, skew: 2.0, ..Profile::default() };
+        let transactions: Vec<Transaction> = Stream::new(profile).take(10_000).collect();
+        let share = |matches: &dyn Fn(&Transaction) -> bool| transactions.iter().filter(|tx| matches(tx)).count() as f64 / 10_000.0;
+
+        // u^3 is below 0.1 for a little under half of all draws
+        let lowest_tenth = share(&|tx| tx.client <= 10);
+        assert!(lowest_tenth > 0.4 && lowest_tenth < 0.5);
+        assert!((share(&|tx| !tx.is_valid()) - 0.05).abs() < 0.01);
+        assert!((share(&|tx| tx.transaction_type == TransactionType::Chargeback) - 0.01).abs() < 0.005);
+    }
+
+    #[test]
+    fn model_should_agree_with_the_processor_on_generated_streams() {
+        let profile = Profile { clients: 50, disputes: 0.1, chargebacks: 0.002, invalid: 0.01, skew: 1.0, ..Profile::default() };
+        let mut processor = TransactionProcessor::new();
+        let mut model = Model::new();
+        for transaction in Stream::new(profile).take(20_000) {
+            let _ = processor.process_transaction(transaction);
+            model.apply(&transaction);
+        }
+        let expected: Vec<(ClientId, i64, i64, bool)> = model
+            .accounts()
+            .map(|(client, account)| (*client, account.available, account.held, account.locked))
+            .collect();
+        let mut accounts: Vec<(ClientId, i64, i64, bool)> = processor
+            .get_account_states()
+            .iter()
+            .map(|account| (account.client, to_units(account.available), to_units(account.held), account.locked))
+            .collect();
+        accounts.sort_by_key(|account| account.0);
+        assert!(expected.iter().any(|account| account.3));
+        assert!(accounts == expected);
+    }
+
+    #[test]
+    fn write_csv_should_write_rows_that_parse_back() {
+        let transactions: Vec<Transaction> = Stream::new(Profile::default()).take(100).collect();
+        let mut bytes = Vec::new();
+        write_csv(&mut bytes, transactions.iter().copied()).unwrap();
+
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(bytes.as_slice());
+        let parsed: Vec<Transaction> = reader.records().map(|record| Transaction::parse(record.unwrap()).unwrap()).collect();
+        assert!(parsed.len() == 100);
+        for (parsed, written) in parsed.iter().zip(transactions.iter()) {
+            assert!(parsed.transaction_type == written.transaction_type);
+            assert!(parsed.tx == written.tx && parsed.client == written.client);
+            assert!(parsed.amount == written.amount);
+        }
+    }
+}
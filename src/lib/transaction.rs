@@ -1,42 +1,231 @@
-use std::{fmt::Error, num::ParseIntError};
-use csv::StringRecord;
+use super::error::ParseError;
+use chrono::DateTime;
+use std::{fmt, num::IntErrorKind, str::FromStr};
+use csv::{ByteRecord, StringRecord};
 
-#[derive(Debug, Clone, Copy)]
+// change these to widen or narrow the id spaces of every module
+pub type ClientId = u64;
+pub type TxId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TransactionType {
     Deposit,
     Withdrawal,
     Dispute,
     Resolve,
     Chargeback,
+    Refund,
+    Reversal,
+    Authorize,
+    Capture,
+    Void,
 }
 
 impl TransactionType {
     fn parse(item: &str) -> Result<TransactionType, String> {
-        return match item {
+        match item {
             "deposit" => Ok(TransactionType::Deposit),
             "withdrawal" => Ok(TransactionType::Withdrawal),
             "dispute" => Ok(TransactionType::Dispute),
             "resolve" => Ok(TransactionType::Resolve),
             "chargeback" => Ok(TransactionType::Chargeback),
+            "refund" => Ok(TransactionType::Refund),
+            "reversal" => Ok(TransactionType::Reversal),
+            "authorize" => Ok(TransactionType::Authorize),
+            "capture" => Ok(TransactionType::Capture),
+            "void" => Ok(TransactionType::Void),
             _ => Err("Not a valid transaction type".into()),
+        }
+    }
+}
+
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Refund => "refund",
+            TransactionType::Reversal => "reversal",
+            TransactionType::Authorize => "authorize",
+            TransactionType::Capture => "capture",
+            TransactionType::Void => "void",
         };
+        write!(f, "{}", name)
     }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Transaction {
     pub transaction_type: TransactionType,
-    pub client: u16,
-    pub tx: u32,
+    pub client: ClientId,
+    pub tx: TxId,
     pub amount: Option<f64>, // u128
     pub disputed: bool,
+    // event time, in milliseconds since the unix epoch
+    pub timestamp: Option<u64>,
+}
+
+// amounts have four decimal places, so they can be compared and summed exactly in ten-thousandths
+const UNITS: f64 = 10_000.0;
+
+pub fn to_units(amount: f64) -> i64 {
+    (amount * UNITS).round() as i64
+}
+
+pub fn from_units(units: i64) -> f64 {
+    units as f64 / UNITS
 }
 
 fn parse_amount(amount_str: &str) -> Option<f64> {
-    return match amount_str.parse::<f64>() {
-        Ok(amount) => Some(amount),
-        Err(_) => None,
+    amount_str.parse::<f64>().ok()
+}
+
+// a client or tx id, telling an id that is too large apart from one that isn't a number
+pub fn parse_id<T>(id_str: &str, name: &str) -> Result<T, String>
+where
+    T: FromStr<Err = std::num::ParseIntError>,
+{
+    match id_str.trim().parse::<T>() {
+        Ok(id) => Ok(id),
+        Err(err) if matches!(err.kind(), IntErrorKind::PosOverflow) => {
+            Err(format!("{} id {} is out of range", name, id_str.trim()))
+        }
+        Err(_) => Err(format!("unable to parse {}", name)),
+    }
+}
+
+// epoch milliseconds, or an RFC 3339 date time
+pub fn parse_timestamp(timestamp_str: &str) -> Result<u64, String> {
+    timestamp_millis(timestamp_str).ok_or_else(|| ParseError::Timestamp.to_string())
+}
+
+fn timestamp_millis(timestamp_str: &str) -> Option<u64> {
+    if let Ok(millis) = timestamp_str.parse::<u64>() {
+        return Some(millis);
+    }
+    match DateTime::parse_from_rfc3339(timestamp_str) {
+        Ok(time) if time.timestamp_millis() >= 0 => Some(time.timestamp_millis() as u64),
+        _ => None,
+    }
+}
+
+/*
+    The byte parser below reads rows the way `Transaction::parse` does, down
+    to which rows it accepts, without allocating. These helpers follow the
+    std parsers they stand in for: `str::trim`, and `u64` and `f64` from_str.
+*/
+
+// a field without the whitespace `str::trim` would remove
+fn trimmed(field: &[u8]) -> &[u8] {
+    // char::is_whitespace in ascii, which is more than u8::is_ascii_whitespace
+    let is_space = |byte: &u8| matches!(byte, b' ' | b'\t' | b'\n' | 0x0b | 0x0c | b'\r');
+    let start = field.iter().position(|byte| !is_space(byte)).unwrap_or(field.len());
+    let end = field.iter().rposition(|byte| !is_space(byte)).map_or(start, |end| end + 1);
+    let field = &field[start..end];
+    // unicode whitespace is rare enough to leave to str::trim
+    match (field.first(), field.last()) {
+        (Some(first), Some(last)) if *first >= 0x80 || *last >= 0x80 => {
+            std::str::from_utf8(field).map_or(field, |field| field.trim().as_bytes())
+        }
+        _ => field,
+    }
+}
+
+fn parse_u64(digits: &[u8]) -> Result<u64, IntErrorKind> {
+    let digits = match digits {
+        [] => return Err(IntErrorKind::Empty),
+        [b'+'] => return Err(IntErrorKind::InvalidDigit),
+        [b'+', rest @ ..] => rest,
+        _ => digits,
     };
+    let mut value: u64 = 0;
+    // errors in the order from_str finds them, digit by digit
+    for byte in digits {
+        let digit = match byte {
+            b'0'..=b'9' => u64::from(byte - b'0'),
+            _ => return Err(IntErrorKind::InvalidDigit),
+        };
+        value = match value.checked_mul(10).and_then(|value| value.checked_add(digit)) {
+            Some(value) => value,
+            None => return Err(IntErrorKind::PosOverflow),
+        };
+    }
+    Ok(value)
+}
+
+fn parse_id_bytes(field: &[u8], invalid: ParseError, out_of_range: ParseError) -> Result<u64, ParseError> {
+    match parse_u64(trimmed(field)) {
+        Ok(id) => Ok(id),
+        Err(IntErrorKind::PosOverflow) => Err(out_of_range),
+        Err(_) => Err(invalid),
+    }
+}
+
+/*
+    Plain decimals, like `12.3456`, with up to 15 significant digits and 22
+    decimal places are an exact integer over an exact power of ten, so one
+    division rounds them as f64::from_str does. Anything else, exponents,
+    `inf`, `NaN` or long digit strings, goes to from_str.
+*/
+fn parse_amount_bytes(field: &[u8]) -> Option<f64> {
+    const POWERS: [f64; 23] = [
+        1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15, 1e16, 1e17, 1e18,
+        1e19, 1e20, 1e21, 1e22,
+    ];
+    let field = trimmed(field);
+    let (negative, digits) = match field {
+        [b'-', rest @ ..] => (true, rest),
+        [b'+', rest @ ..] => (false, rest),
+        _ => (false, field),
+    };
+    let mut mantissa: u64 = 0;
+    let mut significant = 0;
+    let mut decimals = 0;
+    let mut seen_digit = false;
+    let mut seen_point = false;
+    for byte in digits {
+        match byte {
+            b'0'..=b'9' => {
+                mantissa = mantissa * 10 + u64::from(byte - b'0');
+                if mantissa > 0 {
+                    significant += 1;
+                }
+                if seen_point {
+                    decimals += 1;
+                }
+                seen_digit = true;
+            }
+            b'.' if !seen_point => seen_point = true,
+            _ => return std::str::from_utf8(field).ok()?.parse::<f64>().ok(),
+        }
+        if significant > 15 || decimals > 22 {
+            return std::str::from_utf8(field).ok()?.parse::<f64>().ok();
+        }
+    }
+    if !seen_digit {
+        return None;
+    }
+    let amount = mantissa as f64 / POWERS[decimals];
+    Some(if negative { -amount } else { amount })
+}
+
+fn transaction_type_bytes(item: &[u8]) -> Option<TransactionType> {
+    match item {
+        b"deposit" => Some(TransactionType::Deposit),
+        b"withdrawal" => Some(TransactionType::Withdrawal),
+        b"dispute" => Some(TransactionType::Dispute),
+        b"resolve" => Some(TransactionType::Resolve),
+        b"chargeback" => Some(TransactionType::Chargeback),
+        b"refund" => Some(TransactionType::Refund),
+        b"reversal" => Some(TransactionType::Reversal),
+        b"authorize" => Some(TransactionType::Authorize),
+        b"capture" => Some(TransactionType::Capture),
+        b"void" => Some(TransactionType::Void),
+        _ => None,
+    }
 }
 
 // impl From<StringRecord> for Transaction {
@@ -62,13 +251,19 @@ impl Transaction {
         self.disputed = false;
     }
     pub fn is_valid(self) -> bool {
+        if let Some(amount) = self.amount {
+            if !amount.is_finite() || amount < 0.0 {
+                return false;
+            }
+        }
         if (matches!(self.transaction_type, TransactionType::Deposit)
-            || matches!(self.transaction_type, TransactionType::Withdrawal))
+            || matches!(self.transaction_type, TransactionType::Withdrawal)
+            || matches!(self.transaction_type, TransactionType::Authorize))
             && self.amount.is_none()
         {
             return false;
         }
-        return true;
+        true
     }
     pub fn parse(record: StringRecord) -> Result<Transaction, String> {
 
@@ -76,10 +271,7 @@ impl Transaction {
             return Err("unable to parse row".into());
         }
 
-        let client = match record[1].trim().parse::<u16>() {
-            Ok(id) => id,
-            Err(_) => return Err("unable to parse client".into()),
-        };
+        let client: ClientId = parse_id(&record[1], "client")?;
 
         // TODO: What if transaction type is invalid
         let transaction_type = match TransactionType::parse(&record[0]){
@@ -87,28 +279,69 @@ impl Transaction {
             Err(_) => return Err("unable to parse transaction type".into())
         };
 
-        let tx = match record[2].trim().parse::<u32>() {
-            Ok(id) => id,
-            Err(_) => return Err("Unable to parse transaction".into())
+        let tx: TxId = parse_id(&record[2], "transaction")?;
+
+        let timestamp = match record.get(4).map(|t| t.trim()) {
+            Some(t) if !t.is_empty() => Some(parse_timestamp(t)?),
+            _ => None,
         };
 
         let tx = Transaction {
             transaction_type,
             client,
             tx,
-            amount: match record.len() {
-                4 => parse_amount(record[3].trim()),
-                _ => None,
+            amount: match record.get(3) {
+                Some(amount) => parse_amount(amount.trim()),
+                None => None,
             },
             disputed: false,
+            timestamp,
         };
         Ok(tx)
     }
+
+    // parse for a byte record, reused from row to row, which allocates nothing
+    pub fn parse_bytes(record: &ByteRecord) -> Result<Transaction, ParseError> {
+        // the string reader rejects rows that aren't utf-8 before parse sees them
+        if !record.iter().all(|field| std::str::from_utf8(field).is_ok()) {
+            return Err(ParseError::InvalidUtf8);
+        }
+        if record.len() < 3 {
+            return Err(ParseError::Row);
+        }
+
+        let client = parse_id_bytes(&record[1], ParseError::Client, ParseError::ClientOutOfRange)?;
+        let transaction_type = transaction_type_bytes(&record[0]).ok_or(ParseError::TransactionType)?;
+        let tx = parse_id_bytes(&record[2], ParseError::Transaction, ParseError::TransactionOutOfRange)?;
+
+        let timestamp = match record.get(4).map(trimmed) {
+            Some(t) if !t.is_empty() => {
+                let millis = match parse_u64(t) {
+                    Ok(millis) => Some(millis),
+                    Err(_) => std::str::from_utf8(t).ok().and_then(timestamp_millis),
+                };
+                Some(millis.ok_or(ParseError::Timestamp)?)
+            }
+            _ => None,
+        };
+
+        Ok(Transaction {
+            transaction_type,
+            client,
+            tx,
+            amount: record.get(3).and_then(parse_amount_bytes),
+            disputed: false,
+            timestamp,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Transaction, TransactionType};
+    use super::{parse_timestamp, Transaction, TransactionType};
+    use crate::error::ParseError;
+    use csv::{ByteRecord, StringRecord};
+    use proptest::prelude::*;
 
     #[test]
     fn hello_world_test() {
@@ -121,6 +354,26 @@ mod tests {
         assert!(matches!(tx_type.ok().unwrap(), TransactionType::Deposit));
     }
 
+    #[test]
+    fn should_parse_refund_and_reversal() {
+        assert!(TransactionType::parse("refund") == Ok(TransactionType::Refund));
+        assert!(TransactionType::parse("reversal") == Ok(TransactionType::Reversal));
+    }
+
+    #[test]
+    fn shound_return_invalid_if_authorize_has_no_amount() {
+        let tx = Transaction {
+            transaction_type: TransactionType::Authorize,
+            client: 100,
+            tx: 100,
+            amount: None,
+            disputed: false,
+            timestamp: None,
+        };
+
+        assert!(!tx.is_valid());
+    }
+
     #[test]
     fn should_return_err_if_invalid_transaction_type() {
         let tx_type = TransactionType::parse("does_not_exist");
@@ -135,9 +388,10 @@ mod tests {
             tx: 100,
             amount: None,
             disputed: false,
+            timestamp: None,
         };
 
-        assert!(tx.is_valid() == false);
+        assert!(!tx.is_valid());
     }
 
     #[test]
@@ -148,9 +402,25 @@ mod tests {
             tx: 100,
             amount: None,
             disputed: false,
+            timestamp: None,
         };
 
-        assert!(tx.is_valid() == false);
+        assert!(!tx.is_valid());
+    }
+
+    #[test]
+    fn shound_return_invalid_if_amount_is_negative_or_not_a_number() {
+        for amount in [-1.0, f64::NAN, f64::INFINITY] {
+            let tx = Transaction {
+                transaction_type: TransactionType::Deposit,
+                client: 100,
+                tx: 100,
+                amount: Some(amount),
+                disputed: false,
+                timestamp: None,
+            };
+            assert!(!tx.is_valid());
+        }
     }
 
     #[test]
@@ -161,9 +431,131 @@ mod tests {
             tx: 100,
             amount: Some(1005.0),
             disputed: false,
+            timestamp: None,
         };
 
         assert!(tx.is_valid());
     }
 
+    #[test]
+    fn should_parse_wide_ids_and_report_overflow() {
+        let record = StringRecord::from(vec!["deposit", " 70000", " 5000000000", " 1.0"]);
+        let tx = Transaction::parse(record).unwrap();
+        assert!(tx.client == 70000);
+        assert!(tx.tx == 5_000_000_000);
+
+        let record = StringRecord::from(vec!["deposit", " 18446744073709551616", " 1", " 1.0"]);
+        assert!(Transaction::parse(record).unwrap_err() == "client id 18446744073709551616 is out of range");
+        let record = StringRecord::from(vec!["deposit", " 1", " -1", " 1.0"]);
+        assert!(Transaction::parse(record).unwrap_err() == "unable to parse transaction");
+    }
+
+    #[test]
+    fn should_parse_epoch_millis_and_rfc3339_timestamps() {
+        assert!(parse_timestamp("1700000000000").unwrap() == 1_700_000_000_000);
+        assert!(parse_timestamp("2023-11-14T22:13:20Z").unwrap() == 1_700_000_000_000);
+        assert!(parse_timestamp("2023-11-14T23:13:20.5+01:00").unwrap() == 1_700_000_000_500);
+        assert!(parse_timestamp("yesterday").is_err());
+    }
+
+    #[test]
+    fn should_parse_optional_timestamp_column() {
+        let record = StringRecord::from(vec!["deposit", " 1", " 2", " 3.0", " 1700000000000"]);
+        let tx = Transaction::parse(record).unwrap();
+        assert!(tx.amount == Some(3.0));
+        assert!(tx.timestamp == Some(1_700_000_000_000));
+
+        let record = StringRecord::from(vec!["resolve", " 1", " 2", "", "", ""]);
+        let tx = Transaction::parse(record).unwrap();
+        assert!(tx.amount.is_none());
+        assert!(tx.timestamp.is_none());
+
+        let record = StringRecord::from(vec!["deposit", " 1", " 2", " 3.0", " yesterday"]);
+        assert!(Transaction::parse(record).is_err());
+    }
+
+    #[test]
+    fn parse_bytes_should_read_rows_like_parse() {
+        let record = ByteRecord::from(vec!["deposit", " 1", " 2", " 3.0", " 1700000000000"]);
+        let tx = Transaction::parse_bytes(&record).unwrap();
+        assert!(tx.transaction_type == TransactionType::Deposit);
+        assert!((tx.client, tx.tx) == (1, 2));
+        assert!(tx.amount == Some(3.0));
+        assert!(tx.timestamp == Some(1_700_000_000_000));
+
+        let record = ByteRecord::from(vec!["deposit", " 18446744073709551616", " 1", " 1.0"]);
+        assert!(Transaction::parse_bytes(&record).unwrap_err() == ParseError::ClientOutOfRange);
+        let record = ByteRecord::from(vec![&b"deposit"[..], b" 1", b" 2", b" \xff"]);
+        assert!(Transaction::parse_bytes(&record).unwrap_err() == ParseError::InvalidUtf8);
+    }
+
+    // the current parser, from bytes as the csv reader would hand them over
+    fn parse_as_string(record: &ByteRecord) -> Result<Transaction, String> {
+        match StringRecord::from_byte_record(record.clone()) {
+            Ok(record) => Transaction::parse(record),
+            Err(_) => Err(ParseError::InvalidUtf8.to_string()),
+        }
+    }
+
+    fn same(left: &Transaction, right: &Transaction) -> bool {
+        left.transaction_type == right.transaction_type
+            && left.client == right.client
+            && left.tx == right.tx
+            && left.amount.map(f64::to_bits) == right.amount.map(f64::to_bits)
+            && left.timestamp == right.timestamp
+    }
+
+    // fields that sit close to the edges of what parses
+    fn field() -> impl Strategy<Value = Vec<u8>> {
+        let token = prop_oneof![
+            Just("deposit".to_string()),
+            Just("withdrawal".to_string()),
+            Just("dispute".to_string()),
+            Just(" deposit".to_string()),
+            "[ \t\x0b\u{a0}\u{3000}]{0,2}[+-]?[0-9]{0,24}(\\.[0-9]{0,25})?([eE][+-]?[0-9]{1,3})?[ \t\x0b\u{85}]{0,2}",
+            "[ ]?(18446744073709551615|18446744073709551616|99999999999999999999x|\\+|-0|\\+7|0x10)",
+            "[ ]?(NaN|nan|inf|-inf|infinity|\\.|-\\.|\\.5|5\\.|\\+\\.5|1_000|١٢)",
+            "[ ]?(2023-11-14T22:13:20Z|2023-11-14T23:13:20\\.5\\+01:00|1969-12-31T23:59:59Z|yesterday)",
+            ".{0,6}",
+        ];
+        prop_oneof![
+            9 => token.prop_map(String::into_bytes),
+            1 => prop::collection::vec(any::<u8>(), 0..6),
+        ]
+    }
+
+    // mostly rows that get as far as the amount and timestamp
+    fn record() -> impl Strategy<Value = Vec<Vec<u8>>> {
+        let row = (
+            prop_oneof![Just("deposit"), Just("withdrawal"), Just("resolve")],
+            "[ ]?[0-9]{1,5}",
+            "[ ]?[0-9]{1,5}",
+            prop::collection::vec(field(), 0..4),
+        );
+        prop_oneof![
+            3 => row.prop_map(|(kind, client, tx, rest)| {
+                let mut fields = vec![kind.as_bytes().to_vec(), client.into_bytes(), tx.into_bytes()];
+                fields.extend(rest);
+                fields
+            }),
+            1 => prop::collection::vec(field(), 0..6),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn parse_bytes_should_accept_and_reject_what_parse_does(fields in record()) {
+            let record = ByteRecord::from(fields);
+            match (parse_as_string(&record), Transaction::parse_bytes(&record)) {
+                (Ok(expected), Ok(parsed)) => prop_assert!(same(&expected, &parsed), "{:?} != {:?}", expected, parsed),
+                // out of range errors name the id, the static ones can't
+                (Err(expected), Err(err)) if expected.ends_with("is out of range") => {
+                    prop_assert!(matches!(err, ParseError::ClientOutOfRange | ParseError::TransactionOutOfRange));
+                    prop_assert!(expected.starts_with(&err.as_str()[..6]));
+                }
+                (Err(expected), Err(err)) => prop_assert_eq!(expected, err.to_string()),
+                (expected, parsed) => prop_assert!(false, "{:?} != {:?}", expected, parsed),
+            }
+        }
+    }
 }
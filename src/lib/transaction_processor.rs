@@ -1,32 +1,316 @@
-use super::{account::Account, transaction::Transaction, transaction::TransactionType};
-use std::collections::HashMap;
+use super::{
+    account::Account,
+    clients::{ClientConfig, CreditLine},
+    error::ProcessError,
+    rules::{Alert, Clock, RuleSet},
+    transaction::{from_units, to_units, ClientId, Transaction, TransactionType, TxId},
+};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 
+#[derive(Default)]
 pub struct TransactionProcessor {
-    accounts: HashMap<u16, Account>,
-    transactions: HashMap<u32, Transaction>,
+    accounts: HashMap<ClientId, Account>,
+    transactions: HashMap<TxId, Transaction>,
+    rules: RuleSet,
+    alerts: Vec<Alert>,
+    // row number of the transaction being processed
+    row: u64,
+    // latest event time seen, in milliseconds since the unix epoch
+    event_time: u64,
+    // rules run on event time once any row has carried a timestamp
+    timed: bool,
+    reject_out_of_order: bool,
+    // how long after a transaction it can still be disputed, in milliseconds
+    dispute_window: Option<u64>,
+    // ids of transactions dropped from memory once past the dispute window
+    evicted: HashSet<TxId>,
+    dispute_timeout: Option<DisputeTimeout>,
+    // open disputes by tx id, oldest first
+    disputes: HashMap<TxId, Vec<DisputeCase>>,
+    // refunds and reversals by the tx id they refer back to
+    refunds: HashMap<TxId, Vec<Refund>>,
+    // authorizations that still hold funds, by tx id
+    authorizations: HashMap<TxId, Authorization>,
+    // how long an authorization holds funds before it is voided, in milliseconds
+    authorization_expiry: Option<u64>,
+    credit_lines: HashMap<ClientId, CreditLine>,
+    // clients from the clients file, see declare_client
+    declared: HashSet<ClientId>,
+    // transactions for undeclared clients are rejected instead of opening an account
+    strict_clients: bool,
+    // legacy behavior, any row for a client opens its account even if it changes nothing
+    open_on_any_transaction: bool,
+    keep_journal: bool,
+    journal: Vec<Event>,
+}
+
+const MILLIS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+// the account a client opens with, at the current event time
+// interest accrues from `event_time`, or from the first event time seen when there is none yet
+fn new_account(
+    client: ClientId,
+    credit_line: Option<&CreditLine>,
+    event_time: Option<u64>,
+) -> Account {
+    let mut account = Account::new(client);
+    if let Some(credit_line) = credit_line {
+        account.credit_limit = credit_line.limit;
+        account.interest_rate = credit_line.interest_rate;
+    }
+    account.accrued_at = event_time;
+    account
+}
+
+// the amount of a transaction that can't go without one
+fn amount_of(transaction: &Transaction) -> Result<f64, ProcessError> {
+    match transaction.amount {
+        Some(amount) => Ok(amount),
+        None => Err(ProcessError::InvalidAmount(transaction.tx)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeoutAction {
+    Resolve,
+    Chargeback,
+}
+
+// disputes left open for longer than `after` milliseconds are settled by `action`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisputeTimeout {
+    pub after: u64,
+    pub action: TimeoutAction,
+}
+
+// an open dispute against all or part of a transaction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisputeCase {
+    pub amount: f64,
+    // event time the dispute was opened
+    pub opened: u64,
+}
+
+// part or all of a deposit or withdrawal given back by a refund or reversal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Refund {
+    pub transaction_type: TransactionType,
+    pub amount: f64,
+    pub timestamp: u64,
+}
+
+// funds held by an authorize until they are captured or voided
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Authorization {
+    pub client: ClientId,
+    // held amount that is not yet captured or voided
+    pub remaining: f64,
+    pub timestamp: u64,
+}
+
+// a transaction as it was applied to an account, for statements
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    pub transaction: Transaction,
+    // generated by the processor rather than read from the input
+    pub synthetic: bool,
+    // the account once the transaction was applied
+    pub account: Account,
 }
 
 impl TransactionProcessor {
     pub fn new() -> Self {
-        return TransactionProcessor {
-            accounts: HashMap::new(),
-            transactions: HashMap::new(),
-        };
+        TransactionProcessor::default()
+    }
+
+    pub fn with_rules(rules: RuleSet) -> Self {
+        TransactionProcessor {
+            rules,
+            ..TransactionProcessor::default()
+        }
+    }
+
+    // rows older than the latest event time are rejected instead of processed
+    pub fn set_reject_out_of_order(&mut self, reject: bool) {
+        self.reject_out_of_order = reject;
+    }
+
+    pub fn set_dispute_window(&mut self, window: Option<u64>) {
+        self.dispute_window = window;
     }
 
-    fn get_or_create_account(&mut self, client: u16) -> Account {
-        let account_opt = self.accounts.get(&client);
-        match account_opt {
-            Some(account) => *account,
-            None => {
-                let account = Account::new(client);
-                self.accounts.insert(client, account);
-                return account;
+    pub fn set_dispute_timeout(&mut self, timeout: Option<DisputeTimeout>) {
+        self.dispute_timeout = timeout;
+    }
+
+    pub fn set_authorization_expiry(&mut self, expiry: Option<u64>) {
+        self.authorization_expiry = expiry;
+    }
+
+    pub fn set_credit_line(&mut self, client: ClientId, credit_line: CreditLine) {
+        self.credit_lines.insert(client, credit_line);
+        if let Some(account) = self.accounts.get_mut(&client) {
+            account.credit_limit = credit_line.limit;
+            account.interest_rate = credit_line.interest_rate;
+        }
+    }
+
+    // opens the client's account with its declared balances, type and credit line
+    pub fn declare_client(&mut self, config: &ClientConfig) {
+        self.set_credit_line(config.client, config.credit_line);
+        let account = self.get_or_create_account(config.client);
+        account.account_type = config.account_type;
+        account.available = config.available;
+        account.held = config.held;
+        account.total = config.available + config.held;
+        account.locked = config.locked;
+        self.declared.insert(config.client);
+    }
+
+    pub fn set_strict_clients(&mut self, strict: bool) {
+        self.strict_clients = strict;
+    }
+
+    pub fn set_open_on_any_transaction(&mut self, open: bool) {
+        self.open_on_any_transaction = open;
+    }
+
+    // keeps every applied transaction, see get_journal
+    pub fn set_keep_journal(&mut self, keep: bool) {
+        self.keep_journal = keep;
+    }
+
+    pub fn event_time(&self) -> u64 {
+        self.event_time
+    }
+
+    fn clock(&self) -> Clock {
+        if self.timed {
+            Clock {
+                now: self.event_time,
+                day: self.event_time / MILLIS_PER_DAY,
+            }
+        } else {
+            // every row falls on the same day until rows carry a time
+            Clock {
+                now: self.row,
+                day: 0,
             }
         }
     }
 
-    pub fn process_transaction(&mut self, transaction: Transaction) {
+    // the event time, once a row has carried one
+    fn known_time(&self) -> Option<u64> {
+        if self.timed {
+            Some(self.event_time)
+        } else {
+            None
+        }
+    }
+
+    fn get_or_create_account(&mut self, client: ClientId) -> &mut Account {
+        let event_time = self.known_time();
+        let credit_line = self.credit_lines.get(&client);
+        self.accounts
+            .entry(client)
+            .or_insert_with(|| new_account(client, credit_line, event_time))
+    }
+
+    /*
+        Applies `change` to the client's account where it is stored. A client
+        without an account gets a new one, which is only kept if the change
+        applies, so rows that change nothing don't open accounts. Account
+        methods leave the account as it was when they return false.
+    */
+    fn update<F>(&mut self, client: ClientId, change: F) -> bool
+    where
+        F: FnOnce(&mut Account) -> bool,
+    {
+        let event_time = self.known_time();
+        match self.accounts.entry(client) {
+            Entry::Occupied(mut entry) => change(entry.get_mut()),
+            Entry::Vacant(entry) => {
+                let mut account = new_account(client, self.credit_lines.get(&client), event_time);
+                let applied = change(&mut account);
+                if applied {
+                    entry.insert(account);
+                }
+                applied
+            }
+        }
+    }
+
+    fn is_locked(&self, client: ClientId) -> bool {
+        self.accounts
+            .get(&client)
+            .is_some_and(|account| account.locked)
+    }
+
+    pub fn process_transaction(&mut self, mut transaction: Transaction) -> Result<(), ProcessError> {
+        self.row += 1;
+        if self.strict_clients && !self.declared.contains(&transaction.client) {
+            return Err(ProcessError::UnknownClient(transaction.client));
+        }
+        if !transaction.is_valid() {
+            return Err(ProcessError::InvalidAmount(transaction.tx));
+        }
+        if let Some(timestamp) = transaction.timestamp {
+            if timestamp < self.event_time && self.reject_out_of_order {
+                return Err(ProcessError::OutOfOrder {
+                    timestamp,
+                    latest: self.event_time,
+                });
+            }
+            self.event_time = self.event_time.max(timestamp);
+            if !self.timed {
+                // accounts opened so far, such as declared ones, accrue from the first event time
+                for account in self.accounts.values_mut() {
+                    account.accrue_interest(self.event_time);
+                }
+                // and disputes and authorizations opened so far time out from it
+                for case in self.disputes.values_mut().flatten() {
+                    case.opened = self.event_time;
+                }
+                for auth in self.authorizations.values_mut() {
+                    auth.timestamp = self.event_time;
+                }
+                self.timed = true;
+            }
+        }
+        // rows without a timestamp happen at the latest event time, and at no known time before the first one
+        transaction.timestamp = transaction.timestamp.or(self.known_time());
+        self.expire_disputes();
+        self.expire_authorizations();
+        // untimed rows give interest no time to accrue over
+        if let Some(account) = self
+            .accounts
+            .get_mut(&transaction.client)
+            .filter(|_| self.timed)
+        {
+            account.accrue_interest(self.event_time);
+        }
+        let clock = self.clock();
+
+        if !self.rules.is_empty() {
+            // rules see the account the client would open with if it has none yet
+            let opening;
+            let account = match self.accounts.get(&transaction.client) {
+                Some(account) => account,
+                None => {
+                    opening = new_account(
+                        transaction.client,
+                        self.credit_lines.get(&transaction.client),
+                        self.known_time(),
+                    );
+                    &opening
+                }
+            };
+            match self.rules.check(&transaction, account, clock) {
+                Ok(alerts) => self.alerts.extend(alerts),
+                Err(reason) => return Err(ProcessError::RuleViolation(reason)),
+            }
+        }
+
         match transaction.transaction_type {
             TransactionType::Deposit | TransactionType::Withdrawal => {
                 self.transactions.insert(transaction.tx, transaction);
@@ -34,69 +318,428 @@ impl TransactionProcessor {
             _ => {}
         }
 
-        match transaction.transaction_type {
-            TransactionType::Deposit => self.process_deposit(transaction),
-            TransactionType::Withdrawal => self.process_withdrawal(transaction),
-            TransactionType::Dispute => self.process_dispute(transaction),
-            TransactionType::Resolve => self.process_resolve(transaction),
-            TransactionType::Chargeback => self.process_chargeback(transaction),
+        // accounts only open once a transaction changes them, unless kept for compatibility
+        if self.open_on_any_transaction
+            && !matches!(
+                transaction.transaction_type,
+                TransactionType::Refund | TransactionType::Reversal | TransactionType::Capture | TransactionType::Void
+            )
+        {
+            self.get_or_create_account(transaction.client);
         }
+
+        let applied = match transaction.transaction_type {
+            TransactionType::Deposit => self.process_deposit(transaction)?,
+            TransactionType::Withdrawal => self.process_withdrawal(transaction)?,
+            TransactionType::Dispute => self.process_dispute(&mut transaction)?,
+            TransactionType::Resolve => self.process_resolve(&mut transaction)?,
+            TransactionType::Chargeback => self.process_chargeback(&mut transaction)?,
+            TransactionType::Refund | TransactionType::Reversal => self.process_refund(&mut transaction)?,
+            TransactionType::Authorize => self.process_authorize(transaction)?,
+            TransactionType::Capture | TransactionType::Void => self.process_capture(&mut transaction)?,
+        };
+        if applied {
+            self.rules.record(&transaction, clock);
+            self.record_event(transaction, false);
+        }
+        Ok(())
     }
 
-    fn process_deposit(&mut self, transaction: Transaction) {
-        let mut account = self.get_or_create_account(transaction.client);
-        account.deposit(transaction.amount.unwrap());
-        self.accounts.insert(transaction.client, account);
+    // settles disputes that have been open for longer than the timeout
+    fn expire_disputes(&mut self) {
+        let timeout = match self.dispute_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let mut expired: Vec<(u64, TxId, f64)> = Vec::new();
+        for (tx, cases) in self.disputes.iter() {
+            for case in cases {
+                if self.event_time.saturating_sub(case.opened) > timeout.after {
+                    expired.push((case.opened, *tx, case.amount));
+                }
+            }
+        }
+        expired.sort_unstable_by_key(|(opened, tx, _)| (*opened, *tx));
+
+        for (opened, tx, amount) in expired {
+            let client = match self.transactions.get(&tx) {
+                Some(disputed_tx) => disputed_tx.client,
+                None => {
+                    self.disputes.remove(&tx);
+                    continue;
+                }
+            };
+            // cases are settled oldest first, so this settles the expired case
+            let mut synthetic = Transaction {
+                transaction_type: match timeout.action {
+                    TimeoutAction::Resolve => TransactionType::Resolve,
+                    TimeoutAction::Chargeback => TransactionType::Chargeback,
+                },
+                client,
+                tx,
+                amount: Some(amount),
+                disputed: false,
+                timestamp: Some(opened.saturating_add(timeout.after)),
+            };
+            let applied = match timeout.action {
+                TimeoutAction::Resolve => self.process_resolve(&mut synthetic),
+                TimeoutAction::Chargeback => self.process_chargeback(&mut synthetic),
+            };
+            match applied {
+                Ok(true) => self.record_event(synthetic, true),
+                // the account is locked, so the dispute can never be settled
+                _ => {
+                    self.disputes.remove(&tx);
+                    if let Some(disputed_tx) = self.transactions.get_mut(&tx) {
+                        disputed_tx.remove_disputed();
+                    }
+                }
+            }
+        }
     }
-    fn process_withdrawal(&mut self, transaction: Transaction) {
-        let mut account = self.get_or_create_account(transaction.client);
-        account.withdraw(transaction.amount.unwrap());
-        self.accounts.insert(transaction.client, account);
+
+    // voids authorizations that have held funds for longer than the expiry
+    fn expire_authorizations(&mut self) {
+        let expiry = match self.authorization_expiry {
+            Some(expiry) => expiry,
+            None => return,
+        };
+        let mut expired: Vec<(u64, TxId)> = self
+            .authorizations
+            .iter()
+            .filter(|(_, auth)| self.event_time.saturating_sub(auth.timestamp) > expiry)
+            .map(|(tx, auth)| (auth.timestamp, *tx))
+            .collect();
+        expired.sort_unstable();
+
+        for (timestamp, tx) in expired {
+            let client = match self.authorizations.get(&tx) {
+                Some(auth) => auth.client,
+                None => continue,
+            };
+            let mut synthetic = Transaction {
+                transaction_type: TransactionType::Void,
+                client,
+                tx,
+                amount: None,
+                disputed: false,
+                timestamp: Some(timestamp.saturating_add(expiry)),
+            };
+            match self.process_capture(&mut synthetic) {
+                Ok(true) => self.record_event(synthetic, true),
+                // the account is locked, so the funds can never be released
+                _ => {
+                    self.authorizations.remove(&tx);
+                }
+            }
+        }
     }
-    fn process_dispute(&mut self, tx: Transaction) {
-        let mut account = self.get_or_create_account(tx.client);
 
-        if self.transactions.contains_key(&tx.tx){
-            let mut disputed_tx = self.get_transaction(&tx.tx);
-            if tx.client == disputed_tx.client {
-                account.dispute(disputed_tx.amount.unwrap());
-                self.accounts.insert(tx.client, account);
-                
-                disputed_tx.set_disputed();
-                self.transactions.insert(disputed_tx.tx, disputed_tx);
+    // called at the end of the input, to settle disputes that have timed out by `as_of`
+    pub fn finish(&mut self, as_of: Option<u64>) {
+        if let Some(as_of) = as_of {
+            self.event_time = self.event_time.max(as_of);
+        }
+        self.expire_disputes();
+        self.expire_authorizations();
+        if self.timed {
+            for account in self.accounts.values_mut() {
+                account.accrue_interest(self.event_time);
             }
         }
     }
-    fn process_resolve(&mut self, tx: Transaction) {
 
-        let mut account = self.get_or_create_account(tx.client);
+    fn record_event(&mut self, transaction: Transaction, synthetic: bool) {
+        if !self.keep_journal {
+            return;
+        }
+        if let Some(account) = self.accounts.get(&transaction.client) {
+            self.journal.push(Event {
+                transaction,
+                synthetic,
+                account: *account,
+            });
+        }
+    }
+
+    pub fn get_journal(&self) -> &[Event] {
+        &self.journal
+    }
 
-        if self.transactions.contains_key(&tx.tx){
-            let mut disputed_tx = self.get_transaction(&tx.tx);
+    fn process_deposit(&mut self, transaction: Transaction) -> Result<bool, ProcessError> {
+        let amount = amount_of(&transaction)?;
+        let applied = self.update(transaction.client, |account| account.deposit(amount));
+        Ok(applied)
+    }
+    fn process_withdrawal(&mut self, transaction: Transaction) -> Result<bool, ProcessError> {
+        let amount = amount_of(&transaction)?;
+        let applied = self.update(transaction.client, |account| account.withdraw(amount));
+        Ok(applied)
+    }
+    fn process_dispute(&mut self, tx: &mut Transaction) -> Result<bool, ProcessError> {
+        if let Some(disputed_tx) = self.transactions.get(&tx.tx) {
             if tx.client == disputed_tx.client {
-                account.resolve(disputed_tx.amount.unwrap());
-                self.accounts.insert(tx.client, account);
-                
-                disputed_tx.remove_disputed();
-                self.transactions.insert(disputed_tx.tx, disputed_tx);
+                if self.is_expired(disputed_tx) {
+                    return Err(ProcessError::DisputeWindowExpired(disputed_tx.tx));
+                }
+                // only the part of the transaction that is not already under dispute or refunded
+                let undisputed = to_units(amount_of(disputed_tx)?)
+                    - self.disputed_units(tx.tx)
+                    - self.refunded_units(tx.tx);
+                let amount = match tx.amount {
+                    Some(amount) if to_units(amount) <= 0 || to_units(amount) > undisputed => {
+                        return Err(ProcessError::InvalidDisputeAmount {
+                            tx: tx.tx,
+                            amount,
+                            limit: from_units(undisputed),
+                        })
+                    }
+                    Some(amount) => amount,
+                    None if undisputed <= 0 => return Err(ProcessError::AlreadyDisputed(tx.tx)),
+                    None => from_units(undisputed),
+                };
+                let applied = self.update(tx.client, |account| account.dispute(amount));
+                if applied {
+                    self.disputes.entry(tx.tx).or_default().push(DisputeCase {
+                        amount,
+                        opened: self.event_time,
+                    });
+                    if let Some(disputed_tx) = self.transactions.get_mut(&tx.tx) {
+                        disputed_tx.set_disputed();
+                    }
+                    tx.amount = Some(amount);
+                }
+                return Ok(applied);
+            }
+        } else if self.evicted.contains(&tx.tx) {
+            return Err(ProcessError::DisputeWindowExpired(tx.tx));
+        }
+        Ok(false)
+    }
+    fn process_resolve(&mut self, tx: &mut Transaction) -> Result<bool, ProcessError> {
+        if let Some(disputed_tx) = self.transactions.get(&tx.tx) {
+            if tx.client == disputed_tx.client && disputed_tx.disputed && !self.is_locked(tx.client)
+            {
+                let amount = self.settle_disputes(tx.tx, tx.amount)?;
+                let applied = self.update(tx.client, |account| account.resolve(amount));
+                tx.amount = Some(amount);
+                return Ok(applied);
+            }
+        }
+        Ok(false)
+    }
+    fn process_chargeback(&mut self, tx: &mut Transaction) -> Result<bool, ProcessError> {
+        if let Some(disputed_tx) = self.transactions.get(&tx.tx) {
+            if !disputed_tx.disputed {
+                return Ok(false);
+            }
+            if tx.client == disputed_tx.client && !self.is_locked(tx.client) {
+                let amount = self.settle_disputes(tx.tx, tx.amount)?;
+                let applied = self.update(tx.client, |account| account.chargeback(amount));
+                tx.amount = Some(amount);
+                return Ok(applied);
+            }
+        }
+        Ok(false)
+    }
+
+    // gives back part of a deposit or withdrawal, a reversal always gives back all that is left
+    fn process_refund(&mut self, tx: &mut Transaction) -> Result<bool, ProcessError> {
+        let original = match self.transactions.get(&tx.tx) {
+            Some(original) if original.client == tx.client => original,
+            _ => return Ok(false),
+        };
+        let deposited = matches!(original.transaction_type, TransactionType::Deposit);
+        let refundable =
+            to_units(amount_of(original)?) - self.refunded_units(tx.tx) - self.disputed_units(tx.tx);
+        let amount = match (tx.transaction_type, tx.amount) {
+            (TransactionType::Refund, Some(amount)) => {
+                if to_units(amount) <= 0 || to_units(amount) > refundable {
+                    return Err(ProcessError::InvalidRefundAmount {
+                        tx: tx.tx,
+                        amount,
+                        limit: from_units(refundable),
+                    });
+                }
+                amount
+            }
+            _ if refundable <= 0 => return Err(ProcessError::AlreadyRefunded(tx.tx)),
+            _ => from_units(refundable),
+        };
+
+        let applied = self.update(tx.client, |account| {
+            if deposited {
+                account.withdraw(amount)
+            } else {
+                account.deposit(amount)
             }
+        });
+        if applied {
+            self.refunds.entry(tx.tx).or_default().push(Refund {
+                transaction_type: tx.transaction_type,
+                amount,
+                timestamp: self.event_time,
+            });
+            tx.amount = Some(amount);
+        }
+        Ok(applied)
+    }
+
+    fn process_authorize(&mut self, tx: Transaction) -> Result<bool, ProcessError> {
+        let amount = amount_of(&tx)?;
+        let applied = self.update(tx.client, |account| account.authorize(amount));
+        if applied {
+            self.authorizations.insert(
+                tx.tx,
+                Authorization {
+                    client: tx.client,
+                    remaining: amount,
+                    timestamp: self.event_time,
+                },
+            );
+        }
+        Ok(applied)
+    }
+
+    // a capture settles part or all of an authorization, a void releases what is left of it
+    fn process_capture(&mut self, tx: &mut Transaction) -> Result<bool, ProcessError> {
+        let remaining = match self.authorizations.get(&tx.tx) {
+            Some(auth) if auth.client == tx.client => auth.remaining,
+            _ => return Ok(false),
+        };
+        let amount = match (tx.transaction_type, tx.amount) {
+            (TransactionType::Capture, Some(amount)) => {
+                if to_units(amount) <= 0 || to_units(amount) > to_units(remaining) {
+                    return Err(ProcessError::InvalidCaptureAmount {
+                        tx: tx.tx,
+                        amount,
+                        limit: remaining,
+                    });
+                }
+                amount
+            }
+            _ => remaining,
+        };
+
+        let capture = matches!(tx.transaction_type, TransactionType::Capture);
+        let applied = self.update(tx.client, |account| {
+            if capture {
+                account.capture(amount)
+            } else {
+                account.void(amount)
+            }
+        });
+        if applied {
+            let left = to_units(remaining) - to_units(amount);
+            if !capture || left <= 0 {
+                self.authorizations.remove(&tx.tx);
+            } else if let Some(auth) = self.authorizations.get_mut(&tx.tx) {
+                auth.remaining = from_units(left);
+            }
+            tx.amount = Some(amount);
+        }
+        Ok(applied)
+    }
+
+    pub fn get_authorization(&self, tx: &TxId) -> Option<&Authorization> {
+        self.authorizations.get(tx)
+    }
+
+    // in ten-thousandths, so partial amounts add up exactly
+    fn refunded_units(&self, tx: TxId) -> i64 {
+        match self.refunds.get(&tx) {
+            Some(refunds) => refunds.iter().map(|refund| to_units(refund.amount)).sum(),
+            None => 0,
+        }
+    }
+
+    pub fn get_refunds(&self, tx: &TxId) -> &[Refund] {
+        match self.refunds.get(tx) {
+            Some(refunds) => refunds,
+            None => &[],
         }
     }
-    fn process_chargeback(&mut self, tx: Transaction) {
-        let mut account = self.get_or_create_account(tx.client);
-        let disputed_transaction_opt = self.transactions.get(&tx.tx);
-        match disputed_transaction_opt {
-            Some(disputed_tx) => {
-                if !disputed_tx.disputed {
-                    return;
+
+    fn disputed_units(&self, tx: TxId) -> i64 {
+        match self.disputes.get(&tx) {
+            Some(cases) => cases.iter().map(|case| to_units(case.amount)).sum(),
+            None => 0,
+        }
+    }
+
+    // closes `amount` of the open disputes on a tx, oldest first, or all of them when None
+    fn settle_disputes(&mut self, tx: TxId, amount: Option<f64>) -> Result<f64, ProcessError> {
+        let disputed = self.disputed_units(tx);
+        let amount = match amount {
+            Some(amount) if to_units(amount) <= 0 || to_units(amount) > disputed => {
+                return Err(ProcessError::InvalidDisputeAmount {
+                    tx,
+                    amount,
+                    limit: from_units(disputed),
+                })
+            }
+            Some(amount) => amount,
+            None => from_units(disputed),
+        };
+
+        let mut remaining = to_units(amount);
+        if let Some(cases) = self.disputes.get_mut(&tx) {
+            while remaining > 0 {
+                let oldest = match cases.first_mut() {
+                    Some(oldest) => oldest,
+                    None => break,
+                };
+                let units = to_units(oldest.amount);
+                if units <= remaining {
+                    remaining -= units;
+                    cases.remove(0);
+                } else {
+                    oldest.amount = from_units(units - remaining);
+                    remaining = 0;
                 }
-                if tx.client == disputed_tx.client {
-                    account.chargeback(disputed_tx.amount.unwrap());
-                    self.accounts.insert(tx.client, account);
+            }
+            if cases.is_empty() {
+                self.disputes.remove(&tx);
+                if let Some(disputed_tx) = self.transactions.get_mut(&tx) {
+                    disputed_tx.remove_disputed();
                 }
             }
-            None => {}
         }
+        Ok(amount)
+    }
+
+    pub fn get_disputes(&self, tx: &TxId) -> &[DisputeCase] {
+        match self.disputes.get(tx) {
+            Some(cases) => cases,
+            None => &[],
+        }
+    }
+
+    // the transaction can no longer be disputed
+    fn is_expired(&self, tx: &Transaction) -> bool {
+        match (self.dispute_window, tx.timestamp) {
+            (Some(window), Some(timestamp)) => self.event_time.saturating_sub(timestamp) > window,
+            _ => false,
+        }
+    }
+
+    // drops stored transactions that are past the dispute window and not under dispute
+    pub fn evict_expired_transactions(&mut self) -> usize {
+        if self.dispute_window.is_none() {
+            return 0;
+        }
+        let expired: Vec<TxId> = self
+            .transactions
+            .values()
+            .filter(|tx| !tx.disputed && self.is_expired(tx))
+            .map(|tx| tx.tx)
+            .collect();
+        for tx in expired.iter() {
+            self.transactions.remove(tx);
+            self.refunds.remove(tx);
+            self.evicted.insert(*tx);
+        }
+        expired.len()
     }
 
     pub fn get_account_states(self) -> Vec<Account> {
@@ -104,8 +747,16 @@ impl TransactionProcessor {
         accounts
     }
 
-    pub fn get_transaction(&mut self, tx: &u32) -> Transaction {
-        return *self.transactions.get(tx).unwrap();
+    pub fn get_account(&self, client: &ClientId) -> Option<&Account> {
+        self.accounts.get(client)
+    }
+
+    pub fn get_alerts(&self) -> &[Alert] {
+        &self.alerts
+    }
+
+    pub fn get_transaction(&self, tx: &TxId) -> Option<Transaction> {
+        self.transactions.get(tx).copied()
     }
 
 }
@@ -113,46 +764,65 @@ impl TransactionProcessor {
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
-    use crate::lib::{account::Account, transaction::{Transaction, TransactionType}};
-    use super::TransactionProcessor;
+    use crate::{account::Account, error::ProcessError, fraud::DepositThenWithdrawal, rules::{Limit, LimitRule, RuleAction, RuleSet}, transaction::{ClientId, Transaction, TransactionType, TxId}};
+    use super::{DisputeTimeout, TimeoutAction, TransactionProcessor};
+    use crate::clients::{ClientConfig, CreditLine};
 
     #[test]
     fn should_create_new_account() {
         let mut processor = TransactionProcessor::new();
-        assert!(processor.accounts.len() == 0);
+        assert!(processor.accounts.is_empty());
         processor.get_or_create_account(100);
         assert!(processor.accounts.len() == 1);
     }
 
     #[test]
     fn should_return_existing_account() {
-        let mut accounts: HashMap<u16, Account> = HashMap::new();
+        let mut accounts: HashMap<ClientId, Account> = HashMap::new();
         let mut account = Account::new(100);
         account.available = 5.0;
         accounts.insert(100, account);
         let mut processor = TransactionProcessor {
             accounts,
             transactions: HashMap::new(),
+            ..TransactionProcessor::default()
         };
         let returned_account = processor.get_or_create_account(100);
         assert!(returned_account.client == 100);
         assert!(returned_account.available == 5.0);
     }
 
+    #[test]
+    pub fn process_transaction_should_reject_negative_and_non_finite_amounts(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        for (tx, amount) in [(2, -50.0), (3, f64::NAN), (4, f64::INFINITY)] {
+            for transaction_type in [TransactionType::Deposit, TransactionType::Withdrawal] {
+                let result = processor.process_transaction(Transaction { transaction_type, ..deposit(tx, amount) });
+                assert!(result == Err(ProcessError::InvalidAmount(tx)));
+            }
+        }
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!(account.available == 100.0);
+        assert!(account.total == 100.0);
+    }
+
     #[test]
     pub fn process_transaction_should_add_to_transactions(){
         let mut processor = TransactionProcessor {
             accounts: HashMap::new(),
             transactions: HashMap::new(),
+            ..TransactionProcessor::default()
         };
         processor.process_transaction(Transaction {
             transaction_type: TransactionType::Deposit,
             client: 7000,
             tx: 1,
             amount: Some(1.0),
-            disputed: false
-        });
-        assert!(processor.transactions.len() > 0);
+            disputed: false,
+            timestamp: None
+        }).unwrap();
+        assert!(!processor.transactions.is_empty());
     }
 
     #[test]
@@ -160,22 +830,24 @@ mod tests {
         let mut processor = TransactionProcessor {
             accounts: HashMap::new(),
             transactions: HashMap::new(),
+            ..TransactionProcessor::default()
         };
         processor.process_transaction(Transaction {
             transaction_type: TransactionType::Deposit,
             client: 7000,
             tx: 1,
             amount: Some(1.0),
-            disputed: false
-        });
-        assert!(processor.accounts.len() > 0);
+            disputed: false,
+            timestamp: None
+        }).unwrap();
+        assert!(!processor.accounts.is_empty());
         let account = processor.accounts.get(&7000).unwrap();
         assert!(account.available == 1.0);
     }
 
     #[test]
     pub fn process_withdraw_should_call_account_deposit(){
-        let mut accounts: HashMap<u16, Account> = HashMap::new();
+        let mut accounts: HashMap<ClientId, Account> = HashMap::new();
         let client_id = 7000;
         let mut account = Account::new(client_id);
         account.deposit(100.0);
@@ -183,32 +855,739 @@ mod tests {
         let mut processor = TransactionProcessor {
             accounts,
             transactions: HashMap::new(),
+            ..TransactionProcessor::default()
         };
         processor.process_transaction(Transaction {
             transaction_type: TransactionType::Withdrawal,
             client: client_id,
             tx: 1,
             amount: Some(50.0),
-            disputed: false
-        });
-        assert!(processor.accounts.len() > 0);
+            disputed: false,
+            timestamp: None
+        }).unwrap();
+        assert!(!processor.accounts.is_empty());
         let account = processor.accounts.get(&client_id).unwrap();
         assert!(account.available == 50.0);
     }
 
-    // Additional Tests to consider
-    /*
-        process_dispute_should_ignore_transaction_if_it_is_not_related_to_the_same_client
-        process_dispute_should_call_account_dispute
+    #[test]
+    pub fn process_transaction_should_reject_transaction_breaching_a_reject_rule(){
+        let mut processor = TransactionProcessor::with_rules(RuleSet::new(vec![Box::new(LimitRule {
+            client: None,
+            limit: Limit::MaxAmount(100.0),
+            action: RuleAction::Reject,
+        })]));
+        let result = processor.process_transaction(Transaction {
+            transaction_type: TransactionType::Deposit,
+            client: 7000,
+            tx: 1,
+            amount: Some(500.0),
+            disputed: false,
+            timestamp: None
+        });
+        assert!(matches!(result, Err(ProcessError::RuleViolation(_))));
+        assert!(processor.accounts.is_empty());
+        assert!(processor.transactions.is_empty());
+    }
 
-        process_resolve_should_ignore_transaction_if_it_is_not_related_to_the_same_client
-        process_resolve_should_call_account_resolve
-        process_resolve_should_ignore_transaction_if_it_is_not_disputed
+    #[test]
+    pub fn process_transaction_should_apply_and_alert_on_transaction_breaching_a_flag_rule(){
+        let mut processor = TransactionProcessor::with_rules(RuleSet::new(vec![Box::new(LimitRule {
+            client: Some(7000),
+            limit: Limit::MaxAmount(100.0),
+            action: RuleAction::Flag,
+        })]));
+        processor.process_transaction(Transaction {
+            transaction_type: TransactionType::Deposit,
+            client: 7000,
+            tx: 1,
+            amount: Some(500.0),
+            disputed: false,
+            timestamp: None
+        }).unwrap();
+        assert!(processor.accounts.get(&7000).unwrap().available == 500.0);
+        assert!(processor.get_alerts().len() == 1);
+        assert!(processor.get_alerts()[0].tx == 1);
+    }
 
-        process_chargeback_should_ignore_transaction_if_it_is_not_related_to_the_same_client
-        process_chargeback_should_call_account_chargeback
-        process_chargeback_should_ignore_transaction_if_it_is_not_disputed
-    */
+    #[test]
+    pub fn process_transaction_should_evaluate_rules_against_history(){
+        let mut processor = TransactionProcessor::with_rules(RuleSet::new(vec![Box::new(DepositThenWithdrawal {
+            client: None,
+            action: RuleAction::Flag,
+        })]));
+        processor.process_transaction(Transaction {
+            transaction_type: TransactionType::Deposit,
+            client: 7000,
+            tx: 1,
+            amount: Some(500.0),
+            disputed: false,
+            timestamp: None
+        }).unwrap();
+        processor.process_transaction(Transaction {
+            transaction_type: TransactionType::Withdrawal,
+            client: 7000,
+            tx: 2,
+            amount: Some(500.0),
+            disputed: false,
+            timestamp: None
+        }).unwrap();
+        assert!(processor.get_alerts().len() == 1);
+        assert!(processor.get_alerts()[0].rule == "deposit_withdrawal");
+    }
+
+    #[test]
+    pub fn process_transaction_should_store_event_time(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(Transaction {
+            transaction_type: TransactionType::Deposit,
+            client: 7000,
+            tx: 1,
+            amount: Some(1.0),
+            disputed: false,
+            timestamp: Some(1000)
+        }).unwrap();
+        processor.process_transaction(Transaction {
+            transaction_type: TransactionType::Deposit,
+            client: 7000,
+            tx: 2,
+            amount: Some(1.0),
+            disputed: false,
+            timestamp: None
+        }).unwrap();
+        assert!(processor.get_transaction(&1).unwrap().timestamp == Some(1000));
+        assert!(processor.get_transaction(&2).unwrap().timestamp == Some(1000));
+    }
+
+    #[test]
+    pub fn rows_before_the_first_timestamp_should_have_no_event_time(){
+        let mut processor = TransactionProcessor::new();
+        processor.set_keep_journal(true);
+        processor.set_dispute_window(Some(100));
+        processor.set_dispute_timeout(Some(DisputeTimeout { after: 100, action: TimeoutAction::Resolve }));
+        processor.process_transaction(deposit(1, 10.0)).unwrap();
+        processor.process_transaction(deposit(2, 20.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 2, None)).unwrap();
+        processor.process_transaction(Transaction { timestamp: Some(5000), ..deposit(3, 30.0) }).unwrap();
+
+        assert!(processor.get_journal()[0].transaction.timestamp.is_none());
+        assert!(processor.get_transaction(&1).unwrap().timestamp.is_none());
+        // neither past the dispute window nor timed out as of the first event time
+        assert!(processor.evict_expired_transactions() == 0);
+        assert!(processor.get_disputes(&2)[0].opened == 5000);
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, None)).unwrap();
+        assert!(processor.accounts.get(&7000).unwrap().held == 30.0);
+    }
+
+    #[test]
+    pub fn process_transaction_should_reject_out_of_order_rows(){
+        let mut processor = TransactionProcessor::new();
+        processor.set_reject_out_of_order(true);
+        let deposit = Transaction {
+            transaction_type: TransactionType::Deposit,
+            client: 7000,
+            tx: 1,
+            amount: Some(1.0),
+            disputed: false,
+            timestamp: Some(1000)
+        };
+        processor.process_transaction(deposit).unwrap();
+        let result = processor.process_transaction(Transaction { tx: 2, timestamp: Some(999), ..deposit });
+        assert!(matches!(result, Err(ProcessError::OutOfOrder { .. })));
+        assert!(processor.event_time() == 1000);
+        assert!(processor.accounts.get(&7000).unwrap().available == 1.0);
+    }
+
+    #[test]
+    pub fn process_dispute_should_reject_dispute_after_the_window(){
+        let mut processor = TransactionProcessor::new();
+        processor.set_dispute_window(Some(100));
+        let deposit = Transaction {
+            transaction_type: TransactionType::Deposit,
+            client: 7000,
+            tx: 1,
+            amount: Some(1.0),
+            disputed: false,
+            timestamp: Some(1000)
+        };
+        processor.process_transaction(deposit).unwrap();
+        let dispute = Transaction { transaction_type: TransactionType::Dispute, amount: None, ..deposit };
+        processor.process_transaction(Transaction { timestamp: Some(1100), ..dispute }).unwrap();
+        assert!(processor.accounts.get(&7000).unwrap().held == 1.0);
+
+        processor.process_transaction(Transaction { tx: 2, timestamp: Some(1100), ..deposit }).unwrap();
+        let result = processor.process_transaction(Transaction { tx: 2, timestamp: Some(1201), ..dispute });
+        assert!(result == Err(ProcessError::DisputeWindowExpired(2)));
+        assert!(processor.accounts.get(&7000).unwrap().held == 1.0);
+    }
+
+    #[test]
+    pub fn evict_expired_transactions_should_keep_recent_and_disputed_transactions(){
+        let mut processor = TransactionProcessor::new();
+        processor.set_dispute_window(Some(100));
+        let deposit = Transaction {
+            transaction_type: TransactionType::Deposit,
+            client: 7000,
+            tx: 1,
+            amount: Some(1.0),
+            disputed: false,
+            timestamp: Some(1000)
+        };
+        processor.process_transaction(deposit).unwrap();
+        processor.process_transaction(Transaction { tx: 2, ..deposit }).unwrap();
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Dispute, tx: 2, amount: None, ..deposit }).unwrap();
+        processor.process_transaction(Transaction { tx: 3, timestamp: Some(1200), ..deposit }).unwrap();
+
+        assert!(processor.evict_expired_transactions() == 1);
+        assert!(!processor.transactions.contains_key(&1));
+        assert!(processor.transactions.contains_key(&2));
+        assert!(processor.transactions.contains_key(&3));
+
+        let result = processor.process_transaction(Transaction { transaction_type: TransactionType::Dispute, amount: None, ..deposit });
+        assert!(result == Err(ProcessError::DisputeWindowExpired(1)));
+    }
+
+    #[test]
+    pub fn dispute_timeout_should_settle_open_disputes_when_later_rows_arrive(){
+        let mut processor = TransactionProcessor::new();
+        processor.set_keep_journal(true);
+        processor.set_dispute_timeout(Some(DisputeTimeout { after: 100, action: TimeoutAction::Chargeback }));
+        let deposit = Transaction {
+            transaction_type: TransactionType::Deposit,
+            client: 7000,
+            tx: 1,
+            amount: Some(1.0),
+            disputed: false,
+            timestamp: Some(1000)
+        };
+        processor.process_transaction(deposit).unwrap();
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Dispute, amount: None, ..deposit }).unwrap();
+        processor.process_transaction(Transaction { client: 8000, tx: 2, timestamp: Some(1100), ..deposit }).unwrap();
+        assert!(!processor.accounts.get(&7000).unwrap().locked);
+
+        processor.process_transaction(Transaction { client: 8000, tx: 3, timestamp: Some(1101), ..deposit }).unwrap();
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!(account.locked);
+        assert!(account.total == 0.0);
+
+        let synthetic: Vec<_> = processor.get_journal().iter().filter(|e| e.synthetic).collect();
+        assert!(synthetic.len() == 1);
+        assert!(matches!(synthetic[0].transaction.transaction_type, TransactionType::Chargeback));
+        assert!(synthetic[0].transaction.timestamp == Some(1100));
+    }
+
+    #[test]
+    pub fn dispute_timeout_should_close_disputes_it_can_not_settle(){
+        let mut processor = TransactionProcessor::new();
+        processor.set_dispute_window(Some(50));
+        processor.set_dispute_timeout(Some(DisputeTimeout { after: 100, action: TimeoutAction::Resolve }));
+        processor.process_transaction(Transaction { timestamp: Some(1000), ..deposit(1, 100.0) }).unwrap();
+        processor.process_transaction(Transaction { timestamp: Some(1000), ..deposit(2, 50.0) }).unwrap();
+        processor.process_transaction(Transaction { timestamp: Some(1000), ..referencing(TransactionType::Dispute, 1, None) }).unwrap();
+        processor.process_transaction(Transaction { timestamp: Some(1000), ..referencing(TransactionType::Dispute, 2, None) }).unwrap();
+        processor.process_transaction(Transaction { timestamp: Some(1010), ..referencing(TransactionType::Chargeback, 2, None) }).unwrap();
+
+        // the account is locked, so the resolve the timeout makes is refused
+        processor.process_transaction(Transaction { client: 8000, timestamp: Some(1200), ..deposit(3, 1.0) }).unwrap();
+        assert!(processor.get_disputes(&1).is_empty());
+        assert!(!processor.get_transaction(&1).unwrap().disputed);
+        assert!(processor.evict_expired_transactions() == 2);
+        assert!(processor.get_transaction(&1).is_none());
+    }
+
+    #[test]
+    pub fn finish_should_settle_disputes_that_timed_out_after_the_last_row(){
+        let mut processor = TransactionProcessor::new();
+        processor.set_dispute_timeout(Some(DisputeTimeout { after: 100, action: TimeoutAction::Resolve }));
+        let deposit = Transaction {
+            transaction_type: TransactionType::Deposit,
+            client: 7000,
+            tx: 1,
+            amount: Some(1.0),
+            disputed: false,
+            timestamp: Some(1000)
+        };
+        processor.process_transaction(deposit).unwrap();
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Dispute, amount: None, ..deposit }).unwrap();
+        processor.process_transaction(Transaction { client: 8000, tx: 2, timestamp: Some(1200), ..deposit }).unwrap();
+        // the resolve has already happened once the row at 1200 arrived
+        assert!(processor.accounts.get(&7000).unwrap().held == 0.0);
+
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Dispute, timestamp: Some(1200), amount: None, ..deposit }).unwrap();
+        processor.finish(None);
+        assert!(processor.accounts.get(&7000).unwrap().held == 1.0);
+
+        processor.finish(Some(1301));
+        assert!(processor.accounts.get(&7000).unwrap().held == 0.0);
+        assert!(processor.accounts.get(&7000).unwrap().available == 1.0);
+    }
+
+    fn deposit(tx: TxId, amount: f64) -> Transaction {
+        Transaction {
+            transaction_type: TransactionType::Deposit,
+            client: 7000,
+            tx,
+            amount: Some(amount),
+            disputed: false,
+            timestamp: None
+        }
+    }
+
+    fn referencing(transaction_type: TransactionType, tx: TxId, amount: Option<f64>) -> Transaction {
+        Transaction { transaction_type, amount, ..deposit(tx, 0.0) }
+    }
+
+    #[test]
+    pub fn process_dispute_should_track_partial_disputes_individually(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, Some(30.0))).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, Some(50.0))).unwrap();
+
+        let amounts: Vec<f64> = processor.get_disputes(&1).iter().map(|case| case.amount).collect();
+        assert!(amounts == vec![30.0, 50.0]);
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!(account.held == 80.0);
+        assert!(account.available == 20.0);
+    }
+
+    #[test]
+    pub fn process_dispute_should_reject_amount_above_the_undisputed_remainder(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, Some(60.0))).unwrap();
+
+        let result = processor.process_transaction(referencing(TransactionType::Dispute, 1, Some(50.0)));
+        assert!(matches!(result, Err(ProcessError::InvalidDisputeAmount { tx: 1, .. })));
+
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, None)).unwrap();
+        assert!(processor.accounts.get(&7000).unwrap().held == 100.0);
+
+        let result = processor.process_transaction(referencing(TransactionType::Dispute, 1, None));
+        assert!(result == Err(ProcessError::AlreadyDisputed(1)));
+    }
+
+    #[test]
+    pub fn process_resolve_should_settle_partial_amounts_oldest_first(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, Some(30.0))).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, Some(50.0))).unwrap();
+        processor.process_transaction(referencing(TransactionType::Resolve, 1, Some(40.0))).unwrap();
+
+        let amounts: Vec<f64> = processor.get_disputes(&1).iter().map(|case| case.amount).collect();
+        assert!(amounts == vec![40.0]);
+        assert!(processor.accounts.get(&7000).unwrap().held == 40.0);
+
+        processor.process_transaction(referencing(TransactionType::Chargeback, 1, None)).unwrap();
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!(account.locked);
+        assert!(account.total == 60.0);
+        assert!(!processor.get_transaction(&1).unwrap().disputed);
+    }
+
+    #[test]
+    pub fn partial_disputes_should_add_up_to_the_whole_amount(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 0.3)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, Some(0.1))).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, Some(0.2))).unwrap();
+        assert!(processor.get_disputes(&1).len() == 2);
+        assert!(processor.accounts.get(&7000).unwrap().held == 0.1 + 0.2);
+    }
+
+    #[test]
+    pub fn partial_resolves_should_close_the_whole_dispute(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 1.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, Some(0.7))).unwrap();
+        processor.process_transaction(referencing(TransactionType::Resolve, 1, Some(0.6))).unwrap();
+        assert!(processor.get_disputes(&1)[0].amount == 0.1);
+
+        processor.process_transaction(referencing(TransactionType::Resolve, 1, Some(0.1))).unwrap();
+        assert!(processor.get_disputes(&1).is_empty());
+        assert!(!processor.get_transaction(&1).unwrap().disputed);
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!(account.held.abs() < 1e-9);
+        assert!((account.available - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn process_resolve_should_ignore_transaction_if_it_is_not_disputed(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Resolve, 1, None)).unwrap();
+
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!(account.available == 100.0);
+        assert!(account.held == 0.0);
+    }
+
+    #[test]
+    pub fn process_refund_should_credit_a_withdrawal_up_to_what_is_left(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Withdrawal, ..deposit(2, 60.0) }).unwrap();
+        processor.process_transaction(referencing(TransactionType::Refund, 2, Some(20.0))).unwrap();
+        assert!(processor.accounts.get(&7000).unwrap().available == 60.0);
+
+        let result = processor.process_transaction(referencing(TransactionType::Refund, 2, Some(50.0)));
+        assert!(matches!(result, Err(ProcessError::InvalidRefundAmount { tx: 2, .. })));
+
+        processor.process_transaction(referencing(TransactionType::Reversal, 2, None)).unwrap();
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!(account.available == 100.0);
+        assert!(account.total == 100.0);
+
+        let refunds: Vec<f64> = processor.get_refunds(&2).iter().map(|refund| refund.amount).collect();
+        assert!(refunds == vec![20.0, 40.0]);
+        let result = processor.process_transaction(referencing(TransactionType::Reversal, 2, None));
+        assert!(result == Err(ProcessError::AlreadyRefunded(2)));
+    }
+
+    #[test]
+    pub fn partial_refunds_should_add_up_to_the_whole_amount(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 0.6)).unwrap();
+        for amount in [0.1, 0.2, 0.3] {
+            processor.process_transaction(referencing(TransactionType::Refund, 1, Some(amount))).unwrap();
+        }
+        assert!(processor.get_refunds(&1).len() == 3);
+        assert!(processor.accounts.get(&7000).unwrap().total.abs() < 1e-9);
+
+        let result = processor.process_transaction(referencing(TransactionType::Reversal, 1, None));
+        assert!(result == Err(ProcessError::AlreadyRefunded(1)));
+    }
+
+    #[test]
+    pub fn process_reversal_should_debit_a_deposit_and_shrink_what_can_be_disputed(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Refund, 1, Some(30.0))).unwrap();
+        assert!(processor.accounts.get(&7000).unwrap().total == 70.0);
+
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, None)).unwrap();
+        assert!(processor.accounts.get(&7000).unwrap().held == 70.0);
+        let result = processor.process_transaction(referencing(TransactionType::Reversal, 1, None));
+        assert!(result == Err(ProcessError::AlreadyRefunded(1)));
+    }
+
+    #[test]
+    pub fn process_capture_should_settle_part_of_an_authorization_and_void_the_rest(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Authorize, ..deposit(2, 60.0) }).unwrap();
+        assert!(processor.accounts.get(&7000).unwrap().held == 60.0);
+
+        let result = processor.process_transaction(referencing(TransactionType::Capture, 2, Some(70.0)));
+        assert!(matches!(result, Err(ProcessError::InvalidCaptureAmount { tx: 2, .. })));
+
+        processor.process_transaction(referencing(TransactionType::Capture, 2, Some(40.0))).unwrap();
+        assert!(processor.get_authorization(&2).unwrap().remaining == 20.0);
+
+        processor.process_transaction(referencing(TransactionType::Void, 2, None)).unwrap();
+        assert!(processor.get_authorization(&2).is_none());
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!(account.available == 60.0);
+        assert!(account.held == 0.0);
+        assert!(account.total == 60.0);
+    }
 
+    #[test]
+    pub fn partial_captures_should_close_the_authorization(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 1.0)).unwrap();
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Authorize, ..deposit(2, 0.3) }).unwrap();
+        processor.process_transaction(referencing(TransactionType::Capture, 2, Some(0.1))).unwrap();
+        assert!(processor.get_authorization(&2).unwrap().remaining == 0.2);
+
+        processor.process_transaction(referencing(TransactionType::Capture, 2, Some(0.2))).unwrap();
+        assert!(processor.get_authorization(&2).is_none());
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!(account.held.abs() < 1e-9);
+        assert!((account.total - 0.7).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn authorizations_should_be_voided_once_expired(){
+        let mut processor = TransactionProcessor::new();
+        processor.set_keep_journal(true);
+        processor.set_authorization_expiry(Some(100));
+        processor.process_transaction(Transaction { timestamp: Some(1000), ..deposit(1, 100.0) }).unwrap();
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Authorize, timestamp: Some(1000), ..deposit(2, 60.0) }).unwrap();
+
+        processor.finish(Some(1101));
+        assert!(processor.get_authorization(&2).is_none());
+        assert!(processor.accounts.get(&7000).unwrap().available == 100.0);
+        let last = processor.get_journal().last().unwrap();
+        assert!(last.synthetic);
+        assert!(last.transaction.transaction_type == TransactionType::Void);
+    }
+
+    #[test]
+    pub fn process_withdrawal_should_use_the_clients_credit_line(){
+        let mut processor = TransactionProcessor::new();
+        processor.set_credit_line(7000, CreditLine { limit: 100.0, interest_rate: 0.0 });
+        processor.process_transaction(deposit(1, 50.0)).unwrap();
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Withdrawal, ..deposit(2, 120.0) }).unwrap();
+
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!(account.available == -70.0);
+        assert!(account.headroom() == 30.0);
+    }
+
+    #[test]
+    pub fn finish_should_accrue_interest_on_overdrawn_accounts(){
+        let year = 365 * 24 * 60 * 60 * 1000;
+        let mut processor = TransactionProcessor::new();
+        processor.set_credit_line(7000, CreditLine { limit: 1000.0, interest_rate: 0.1 });
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Withdrawal, timestamp: Some(year), ..deposit(1, 500.0) }).unwrap();
+        processor.finish(Some(2 * year));
+
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!((account.available + 550.0).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn interest_should_not_depend_on_the_number_of_rows(){
+        let year: u64 = 365 * 24 * 60 * 60 * 1000;
+        let overdraft_repaid_after_a_year = |rows: u64| {
+            let mut processor = TransactionProcessor::new();
+            processor.set_credit_line(7000, CreditLine { limit: 1000.0, interest_rate: 0.5 });
+            processor.process_transaction(Transaction { transaction_type: TransactionType::Withdrawal, timestamp: Some(year), ..deposit(1, 100.0) }).unwrap();
+            for row in 1..=rows {
+                let timestamp = year + year * row / (rows + 1);
+                processor.process_transaction(Transaction { timestamp: Some(timestamp), ..deposit(1 + row, 0.0) }).unwrap();
+            }
+            processor.process_transaction(Transaction { timestamp: Some(2 * year), ..deposit(100, 100.0) }).unwrap();
+            processor.accounts.get(&7000).unwrap().available
+        };
+        assert!((overdraft_repaid_after_a_year(0) + 50.0).abs() < 1e-9);
+        assert!((overdraft_repaid_after_a_year(11) + 50.0).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn interest_should_only_accrue_from_the_first_timed_row(){
+        let year = 365 * 24 * 60 * 60 * 1000;
+        let mut processor = TransactionProcessor::new();
+        processor.set_credit_line(7000, CreditLine { limit: 500.0, interest_rate: 0.1 });
+        // untimed, so it happens at no known time
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Withdrawal, ..deposit(1, 100.0) }).unwrap();
+        processor.process_transaction(Transaction { timestamp: Some(53 * year), ..deposit(2, 10.0) }).unwrap();
+        assert!(processor.accounts.get(&7000).unwrap().available == -90.0);
+
+        processor.finish(Some(54 * year));
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!((account.available + 99.0).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn declare_client_should_open_the_account_with_its_balances(){
+        let mut processor = TransactionProcessor::new();
+        processor.declare_client(&ClientConfig { client: 7000, available: 80.0, held: 20.0, ..ClientConfig::default() });
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Withdrawal, ..deposit(1, 50.0) }).unwrap();
+
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!(account.available == 30.0);
+        assert!(account.held == 20.0);
+        assert!(account.total == 50.0);
+    }
+
+    #[test]
+    pub fn declared_clients_should_accrue_interest_from_the_first_event_time(){
+        let year = 365 * 24 * 60 * 60 * 1000;
+        let mut processor = TransactionProcessor::new();
+        let overdrawn = ClientConfig { available: -100.0, credit_line: CreditLine { limit: 500.0, interest_rate: 0.1 }, ..ClientConfig::default() };
+        processor.declare_client(&ClientConfig { client: 7000, ..overdrawn });
+        processor.declare_client(&ClientConfig { client: 7001, ..overdrawn });
+        processor.process_transaction(Transaction { timestamp: Some(53 * year), ..deposit(1, 10.0) }).unwrap();
+        assert!(processor.accounts.get(&7000).unwrap().available == -90.0);
+
+        // 7001 has no rows, but is charged from the first event time all the same
+        processor.finish(Some(54 * year));
+        assert!((processor.accounts.get(&7000).unwrap().available + 99.0).abs() < 1e-9);
+        assert!((processor.accounts.get(&7001).unwrap().available + 110.0).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn strict_clients_should_reject_undeclared_clients(){
+        let mut processor = TransactionProcessor::new();
+        processor.set_strict_clients(true);
+        processor.declare_client(&ClientConfig { client: 8000, locked: true, ..ClientConfig::default() });
+
+        let result = processor.process_transaction(deposit(1, 50.0));
+        assert!(result == Err(ProcessError::UnknownClient(7000)));
+        assert!(!processor.accounts.contains_key(&7000));
+        assert!(processor.process_transaction(Transaction { client: 8000, ..deposit(2, 50.0) }).is_ok());
+        assert!(processor.accounts.get(&8000).unwrap().total == 0.0);
+    }
+
+    #[test]
+    pub fn process_transaction_should_not_open_accounts_for_no_op_rows(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(referencing(TransactionType::Chargeback, 1, None)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, None)).unwrap();
+        processor.process_transaction(Transaction { transaction_type: TransactionType::Withdrawal, ..deposit(2, 50.0) }).unwrap();
+        assert!(processor.accounts.is_empty());
+
+        processor.process_transaction(deposit(3, 50.0)).unwrap();
+        assert!(processor.accounts.len() == 1);
+    }
+
+    #[test]
+    pub fn open_on_any_transaction_should_keep_the_legacy_accounts(){
+        let mut processor = TransactionProcessor::new();
+        processor.set_open_on_any_transaction(true);
+        processor.process_transaction(referencing(TransactionType::Chargeback, 1, None)).unwrap();
 
+        let account = processor.accounts.get(&7000).unwrap();
+        assert!(account.total == 0.0);
+        assert!(!account.locked);
+    }
+
+    // xorshift, so the fuzz test is repeatable without a dependency
+    fn next(seed: &mut u64) -> u64 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        *seed
+    }
+
+    // reads and processes bytes the way main does
+    fn process_bytes(bytes: &[u8], seed: u64) {
+        let mut processor = TransactionProcessor::new();
+        processor.set_dispute_window(Some(seed % 5000));
+        processor.set_dispute_timeout(Some(DisputeTimeout { after: seed % 3000, action: TimeoutAction::Chargeback }));
+        processor.set_authorization_expiry(Some(seed % 2000));
+        processor.set_credit_line(1, CreditLine { limit: 10.0, interest_rate: 0.5 });
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(bytes);
+        let mut record = csv::ByteRecord::new();
+        loop {
+            match reader.read_byte_record(&mut record) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(_) => continue,
+            }
+            match Transaction::parse_bytes(&record) {
+                Ok(transaction) if transaction.is_valid() => {
+                    let _ = processor.process_transaction(transaction);
+                }
+                _ => {}
+            }
+        }
+        processor.evict_expired_transactions();
+        processor.finish(Some(u64::MAX));
+    }
+
+    #[test]
+    pub fn arbitrary_bytes_should_never_panic(){
+        let sample = include_bytes!("../../transactions.csv");
+        let alphabet = b"0123456789,. \n-\"\xff\xc3abcdefghiklmnoprstuvwyz:TZ+";
+        let mut seed = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..2000 {
+            // random bytes, mostly from characters that appear in real rows
+            let len = (next(&mut seed) % 400) as usize;
+            let bytes: Vec<u8> = (0..len)
+                .map(|_| match next(&mut seed) % 10 {
+                    0 => next(&mut seed) as u8,
+                    _ => alphabet[(next(&mut seed) % alphabet.len() as u64) as usize],
+                })
+                .collect();
+            process_bytes(&bytes, next(&mut seed));
+
+            // the sample with a few bytes changed
+            let mut bytes = sample.to_vec();
+            for _ in 0..(next(&mut seed) % 8) {
+                let index = (next(&mut seed) % bytes.len() as u64) as usize;
+                bytes[index] = alphabet[(next(&mut seed) % alphabet.len() as u64) as usize];
+            }
+            process_bytes(&bytes, next(&mut seed));
+        }
+    }
+
+    // a dispute, resolve or chargeback from client 8000 of a deposit of client 7000
+    fn from_other_client(transaction_type: TransactionType, tx: TxId) -> Transaction {
+        Transaction { client: 8000, ..referencing(transaction_type, tx, None) }
+    }
+
+    fn balances(processor: &TransactionProcessor) -> (f64, f64, f64, bool) {
+        let account = processor.accounts.get(&7000).unwrap();
+        (account.available, account.held, account.total, account.locked)
+    }
+
+    #[test]
+    pub fn process_dispute_should_ignore_transaction_if_it_is_not_related_to_the_same_client(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(from_other_client(TransactionType::Dispute, 1)).unwrap();
+
+        assert!(balances(&processor) == (100.0, 0.0, 100.0, false));
+        assert!(processor.get_disputes(&1).is_empty());
+        assert!(!processor.accounts.contains_key(&8000));
+    }
+
+    #[test]
+    pub fn process_dispute_should_call_account_dispute(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, None)).unwrap();
+
+        assert!(balances(&processor) == (0.0, 100.0, 100.0, false));
+    }
+
+    #[test]
+    pub fn process_resolve_should_ignore_transaction_if_it_is_not_related_to_the_same_client(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, None)).unwrap();
+        processor.process_transaction(from_other_client(TransactionType::Resolve, 1)).unwrap();
+
+        assert!(balances(&processor) == (0.0, 100.0, 100.0, false));
+        assert!(!processor.accounts.contains_key(&8000));
+    }
+
+    #[test]
+    pub fn process_resolve_should_call_account_resolve(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, None)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Resolve, 1, None)).unwrap();
+
+        assert!(balances(&processor) == (100.0, 0.0, 100.0, false));
+        assert!(processor.get_disputes(&1).is_empty());
+    }
+
+    #[test]
+    pub fn process_chargeback_should_ignore_transaction_if_it_is_not_related_to_the_same_client(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, None)).unwrap();
+        processor.process_transaction(from_other_client(TransactionType::Chargeback, 1)).unwrap();
+
+        assert!(balances(&processor) == (0.0, 100.0, 100.0, false));
+        assert!(!processor.accounts.contains_key(&8000));
+    }
+
+    #[test]
+    pub fn process_chargeback_should_call_account_chargeback(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(deposit(2, 30.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Dispute, 1, None)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Chargeback, 1, None)).unwrap();
+
+        assert!(balances(&processor) == (30.0, 0.0, 30.0, true));
+        // nothing changes a locked account
+        processor.process_transaction(deposit(3, 10.0)).unwrap();
+        assert!(balances(&processor) == (30.0, 0.0, 30.0, true));
+    }
+
+    #[test]
+    pub fn process_chargeback_should_ignore_transaction_if_it_is_not_disputed(){
+        let mut processor = TransactionProcessor::new();
+        processor.process_transaction(deposit(1, 100.0)).unwrap();
+        processor.process_transaction(referencing(TransactionType::Chargeback, 1, None)).unwrap();
+
+        assert!(balances(&processor) == (100.0, 0.0, 100.0, false));
+    }
 }
@@ -1,39 +1,746 @@
 extern crate csv;
 
-use std::env;
-use lib::transaction::{Transaction};
-use lib::transaction_processor::TransactionProcessor;
+mod cli;
 
-mod lib;
+use cli::{Command, DiffArgs, Format, GenArgs, InputArgs, PolicyArgs, ProcessArgs, ReconcileArgs, RunArgs};
+use csv::ByteRecord;
+use rust_tx::clients::{load_clients, ClientConfig, CreditLine};
+use rust_tx::diff::{diff_account, diff_accounts, Difference};
+use rust_tx::input::{expand_inputs, open_input, FileStats, Reject};
+use rust_tx::model::{Model, ModelAccount};
+use rust_tx::reconcile::{load_balances, mismatched_clients, reconcile, Balance, Discrepancy, Issue};
+use rust_tx::reorder::ReorderBuffer;
+use rust_tx::rules::{Alert, RuleSet};
+use rust_tx::synthetic::{write_csv, Profile, Stream};
+use rust_tx::transaction::{from_units, ClientId, Transaction};
+use rust_tx::transaction_processor::{DisputeTimeout, Event, TransactionProcessor};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+const MILLIS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+// rows between sweeps of transactions past the dispute window
+const EVICTION_INTERVAL: usize = 10_000;
+
+// exit codes, 0 when everything was processed
+const EXIT_FATAL: i32 = 1;
+const EXIT_INCOMPLETE: i32 = 2;
+
+// how a command finished, when it didn't fail
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Complete,
+    // some rows were rejected, or the compared files differ
+    Incomplete,
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let filename = args.last().unwrap();
-
-    // load the file
-    let mut reader = csv::ReaderBuilder::new()
-    .flexible(true)
-    .from_path(filename)
-    .unwrap();
-
-    let mut processor = TransactionProcessor::new();
-
-    for result in reader.records() {
-        let record = result.unwrap();
-        match Transaction::parse(record) {
-            Ok(transaction) => {
-                if transaction.is_valid() {
-                    processor.process_transaction(transaction);
+    let cli = match cli::parse(env::args().collect()) {
+        Ok(cli) => cli,
+        Err(err) => {
+            let _ = err.print();
+            // --help and --version are not errors
+            std::process::exit(if err.use_stderr() { EXIT_FATAL } else { 0 });
+        }
+    };
+    let result = match cli.command {
+        Command::Process(args) => process(args),
+        Command::Validate(args) => validate(args),
+        Command::Statement(args) => statement(args),
+        Command::Snapshot(args) => snapshot(args),
+        Command::Diff(args) => diff(args),
+        Command::Gen(args) => generate(args),
+        Command::Reconcile(args) => reconciliation(args),
+    };
+    match result {
+        Ok(Outcome::Complete) => {}
+        Ok(Outcome::Incomplete) => std::process::exit(EXIT_INCOMPLETE),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(EXIT_FATAL);
+        }
+    }
+}
+
+fn process(args: ProcessArgs) -> Result<Outcome, String> {
+    let (processor, clients, outcome) = run(&args.run, args.statement.is_some())?;
+    if let Some(path) = args.statement.as_ref() {
+        let writer = csv::Writer::from_path(path).map_err(|err| format!("unable to write statement: {}", err))?;
+        write_statement(writer, processor.get_journal()).map_err(|err| format!("unable to write statement: {}", err))?;
+    }
+    write_accounts(io::stdout().lock(), args.format, !clients.is_empty(), processor)
+        .map_err(|err| format!("unable to write accounts: {}", err))?;
+    Ok(outcome)
+}
+
+fn statement(args: RunArgs) -> Result<Outcome, String> {
+    let (processor, _, outcome) = run(&args, true)?;
+    write_statement(csv::Writer::from_writer(io::stdout()), processor.get_journal())
+        .map_err(|err| format!("unable to write statement: {}", err))?;
+    Ok(outcome)
+}
+
+// the accounts as a clients file, so a later run can open with them
+fn snapshot(args: RunArgs) -> Result<Outcome, String> {
+    let (processor, clients, outcome) = run(&args, false)?;
+    let mut snapshot: Vec<ClientConfig> = processor
+        .get_account_states()
+        .into_iter()
+        .map(|account| {
+            // limits are not kept on the account, so they come from the clients file
+            let declared = clients.iter().find(|client| client.client == account.client);
+            ClientConfig {
+                client: account.client,
+                account_type: account.account_type,
+                available: account.available,
+                held: account.held,
+                locked: account.locked,
+                credit_line: CreditLine {
+                    limit: account.credit_limit,
+                    interest_rate: account.interest_rate,
+                },
+                max_amount: declared.and_then(|client| client.max_amount),
+                daily_withdrawal: declared.and_then(|client| client.daily_withdrawal),
+            }
+        })
+        .collect();
+    snapshot.sort_unstable_by_key(|client| client.client);
+    write_snapshot(csv::Writer::from_writer(io::stdout()), &snapshot)
+        .map_err(|err| format!("unable to write snapshot: {}", err))?;
+    Ok(outcome)
+}
+
+fn diff(args: DiffArgs) -> Result<Outcome, String> {
+    if !args.replay.is_empty() {
+        return replay(&args);
+    }
+    // clap can't require --replay for these while the files conflict with it
+    if args.order.is_some() || args.left_config.is_some() || args.right_config.is_some() || args.left_clients.is_some() || args.right_clients.is_some() {
+        return Err("--order and the --left and --right options only apply to --replay".into());
+    }
+    let (left, right) = match (args.left.as_ref(), args.right.as_ref()) {
+        (Some(left), Some(right)) => (load_clients(left)?, load_clients(right)?),
+        _ => return Err("diff needs two files, or --replay".into()),
+    };
+    let differences = diff_accounts(&left, &right);
+    write_differences(io::stdout().lock(), &differences).map_err(|err| format!("unable to write differences: {}", err))?;
+    if differences.is_empty() {
+        Ok(Outcome::Complete)
+    } else {
+        Ok(Outcome::Incomplete)
+    }
+}
+
+// one configuration of a replay
+struct Side {
+    processor: TransactionProcessor,
+    reorder: Option<ReorderBuffer<(usize, u64)>>,
+    as_of: Option<u64>,
+    rows: usize,
+}
+
+impl Side {
+    // the config's policy, opening with `clients` instead of its clients file when given
+    fn new(config: Option<&PathBuf>, clients: Option<&PathBuf>) -> Result<(Side, Vec<ClientConfig>), String> {
+        let mut policy = cli::load_policy(config)?;
+        if clients.is_some() {
+            policy.clients = clients.cloned();
+        }
+        let (processor, clients) = build_processor(&policy)?;
+        let side = Side {
+            processor,
+            reorder: policy.reorder_window.map(ReorderBuffer::new),
+            as_of: policy.as_of,
+            rows: 0,
+        };
+        Ok((side, clients))
+    }
+
+    // processes the rows the reorder buffer lets through, with where they were read from and how they went
+    fn step(&mut self, transaction: Transaction, origin: (usize, u64)) -> Vec<(Transaction, (usize, u64), String)> {
+        let ready = match self.reorder.as_mut() {
+            Some(buffer) => buffer.push(transaction, origin),
+            None => vec![(transaction, origin)],
+        };
+        self.apply(ready)
+    }
+
+    fn apply(&mut self, ready: Vec<(Transaction, (usize, u64))>) -> Vec<(Transaction, (usize, u64), String)> {
+        let mut results = Vec::with_capacity(ready.len());
+        for (transaction, origin) in ready {
+            if self.rows.is_multiple_of(EVICTION_INTERVAL) {
+                self.processor.evict_expired_transactions();
+            }
+            self.rows += 1;
+            let result = match self.processor.process_transaction(transaction) {
+                Ok(()) => "ok".to_string(),
+                Err(err) => format!("rejected, {}", err),
+            };
+            results.push((transaction, origin, result));
+        }
+        results
+    }
+
+    // the rows still held back, then what is settled at the end of the input
+    fn finish(&mut self) {
+        if let Some(mut buffer) = self.reorder.take() {
+            let ready = buffer.drain();
+            self.apply(ready);
+        }
+        self.processor.finish(self.as_of);
+    }
+
+    // only what diff compares
+    fn accounts(self) -> Vec<ClientConfig> {
+        self.processor
+            .get_account_states()
+            .into_iter()
+            .map(|account| ClientConfig {
+                client: account.client,
+                available: account.available,
+                held: account.held,
+                locked: account.locked,
+                ..ClientConfig::default()
+            })
+            .collect()
+    }
+}
+
+// the first row after which the two sides no longer agree
+struct Divergence {
+    transaction: Transaction,
+    origin: (usize, u64),
+    // how each side took the row, if it has processed it yet
+    left: Option<String>,
+    right: Option<String>,
+    differences: Vec<Difference>,
+}
+
+/*
+    Replays the inputs through two configurations and compares the accounts
+    they end with. Row by row, the clients of the rows each side processed
+    are compared as well, to find the first row the sides take differently,
+    or after which a client that matched no longer does. Clients that open
+    with different balances, as from two snapshots, only count once they
+    have matched.
+*/
+fn replay(args: &DiffArgs) -> Result<Outcome, String> {
+    let (mut left, left_clients) = Side::new(args.left_config.as_ref(), args.left_clients.as_ref())?;
+    let (mut right, right_clients) = Side::new(args.right_config.as_ref(), args.right_clients.as_ref())?;
+    let mut differing: HashSet<ClientId> =
+        diff_accounts(&left_clients, &right_clients).iter().map(|difference| difference.client).collect();
+    let opened_apart = !differing.is_empty();
+    // how each side took a row, until both have processed it
+    let mut results: HashMap<(usize, u64), (Option<String>, Option<String>)> = HashMap::new();
+    let mut divergence: Option<Divergence> = None;
+
+    let input = InputArgs {
+        inputs: args.replay.clone(),
+        order: args.order,
+        stats: None,
+        rejects: None,
+    };
+    let ingest = read_inputs(&input, false, |transaction, origin, _| {
+        let processed = [left.step(transaction, origin), right.step(transaction, origin)];
+        if divergence.is_some() {
+            return;
+        }
+        let mut rows = Vec::new();
+        for (side, side_results) in processed.into_iter().enumerate() {
+            for (transaction, origin, result) in side_results {
+                let entry = results.entry(origin).or_default();
+                if side == 0 {
+                    entry.0 = Some(result);
+                } else {
+                    entry.1 = Some(result);
                 }
+                rows.push((transaction, origin));
+            }
+        }
+        for (transaction, origin) in rows {
+            let client = transaction.client;
+            let differences = diff_account(client, left.processor.get_account(&client), right.processor.get_account(&client));
+            let taken = results.get(&origin).cloned().unwrap_or_default();
+            let rejected_differently = matches!(&taken, (Some(left), Some(right)) if left != right);
+            if differences.is_empty() {
+                differing.remove(&client);
             }
-            Err(_) => {
-                // println!("Ignoring: {:?}", record);
-                // ignore the invalid transaction
+            if rejected_differently || (!differences.is_empty() && differing.insert(client)) {
+                divergence = Some(Divergence {
+                    transaction,
+                    origin,
+                    left: taken.0,
+                    right: taken.1,
+                    differences,
+                });
+                return;
+            }
+            if let (Some(_), Some(_)) = taken {
+                results.remove(&origin);
             }
         }
+    })?;
+    left.finish();
+    right.finish();
+
+    let differences = diff_accounts(&left.accounts(), &right.accounts());
+    let mut out = io::stdout().lock();
+    write_differences(&mut out, &differences)
+        .and_then(|_| write_divergence(&mut out, &ingest.stats, divergence.as_ref(), !differences.is_empty(), opened_apart))
+        .map_err(|err| format!("unable to write differences: {}", err))?;
+    if differences.is_empty() && divergence.is_none() {
+        Ok(Outcome::Complete)
+    } else {
+        Ok(Outcome::Incomplete)
     }
-    println!("client, available, held, total, locked");
-    for account in processor.get_account_states() {
-        println!("{}, {:.4}, {:.4}, {:.4}, {}", account.client, account.available, account.held, account.total, account.locked);
+}
+
+// the accounts against finance's closing balances, what doesn't reconcile goes to stdout and a summary to stderr
+fn reconciliation(args: ReconcileArgs) -> Result<Outcome, String> {
+    // read first, so a bad file fails before the inputs are processed
+    let expected = load_balances(&args.expected)?;
+    let (processor, _, outcome) = run(&args.run, false)?;
+    let actual: Vec<Balance> = processor.get_account_states().iter().map(Balance::from).collect();
+    let discrepancies = reconcile(&expected, &actual, args.tolerance);
+    write_discrepancies(io::stdout().lock(), &discrepancies).map_err(|err| format!("unable to write discrepancies: {}", err))?;
+
+    let count = |issue: Issue| discrepancies.iter().filter(|discrepancy| discrepancy.issue == issue).count();
+    let mismatched = mismatched_clients(&discrepancies);
+    eprintln!(
+        "{} expected clients, {} mismatched, {} missing, {} unexpected",
+        expected.len(),
+        mismatched.len(),
+        count(Issue::Missing),
+        count(Issue::Unexpected)
+    );
+    if discrepancies.is_empty() {
+        Ok(outcome)
+    } else {
+        Ok(Outcome::Incomplete)
+    }
+}
+
+// a synthetic stream, with the accounts the reference model ends up with
+fn generate(args: GenArgs) -> Result<Outcome, String> {
+    if args.withdrawal_rate + args.dispute_rate + args.chargeback_rate > 1.0 {
+        return Err("withdrawal, dispute and chargeback rates add up to more than 1".into());
+    }
+    let profile = Profile {
+        clients: args.clients,
+        withdrawals: args.withdrawal_rate,
+        disputes: args.dispute_rate,
+        chargebacks: args.chargeback_rate,
+        invalid: args.invalid_rate,
+        skew: args.skew.max(0.0),
+        seed: args.seed,
+    };
+    let mut model = args.expected.as_ref().map(|_| Model::new());
+    let stream = Stream::new(profile).take(args.rows as usize).inspect(|transaction| {
+        if let Some(model) = model.as_mut() {
+            model.apply(transaction);
+        }
+    });
+    write_csv(io::BufWriter::new(io::stdout().lock()), stream).map_err(|err| format!("unable to write transactions: {}", err))?;
+
+    if let (Some(path), Some(model)) = (args.expected.as_ref(), model) {
+        let file = fs::File::create(path).map_err(|err| format!("unable to write {}: {}", path.display(), err))?;
+        write_expected(io::BufWriter::new(file), model.accounts())
+            .map_err(|err| format!("unable to write {}: {}", path.display(), err))?;
+    }
+    Ok(Outcome::Complete)
+}
+
+// parses every row without processing it, each reject is also reported on stderr
+fn validate(args: InputArgs) -> Result<Outcome, String> {
+    let mut ingest = read_inputs(&args, true, |_, (file, _), ingest| {
+        ingest.stats[file].processed += 1;
+    })?;
+    let rejects = ingest.rejects.take().unwrap_or_default();
+    for reject in rejects.iter() {
+        eprintln!("{}:{}: {}", ingest.stats[reject.file].path.display(), reject.line, reject.reason);
+    }
+    ingest.rejects = Some(rejects);
+    ingest.write(&args)
+}
+
+fn build_processor(policy: &PolicyArgs) -> Result<(TransactionProcessor, Vec<ClientConfig>), String> {
+    let mut rules = match policy.rules.as_ref() {
+        Some(path) => RuleSet::from_path(path)?,
+        None => RuleSet::default(),
+    };
+    let clients = match policy.clients.as_ref() {
+        Some(path) => load_clients(path)?,
+        None => Vec::new(),
+    };
+    for client in clients.iter() {
+        for rule in client.limit_rules() {
+            rules.push(Box::new(rule));
+        }
+    }
+
+    let mut processor = TransactionProcessor::with_rules(rules);
+    processor.set_reject_out_of_order(policy.reject_out_of_order);
+    processor.set_dispute_window(policy.dispute_window.map(|days| days.saturating_mul(MILLIS_PER_DAY)));
+    processor.set_dispute_timeout(policy.dispute_timeout.map(|days| DisputeTimeout {
+        after: days.saturating_mul(MILLIS_PER_DAY),
+        action: policy.timeout_action,
+    }));
+    processor.set_authorization_expiry(policy.authorization_expiry.map(|days| days.saturating_mul(MILLIS_PER_DAY)));
+    for client in clients.iter() {
+        processor.declare_client(client);
+    }
+    processor.set_strict_clients(policy.strict_clients);
+    processor.set_open_on_any_transaction(policy.legacy_accounts);
+    Ok((processor, clients))
+}
+
+// processes every input, writing stats, rejects and alerts
+fn run(args: &RunArgs, keep_journal: bool) -> Result<(TransactionProcessor, Vec<ClientConfig>, Outcome), String> {
+    let (mut processor, clients) = build_processor(&args.policy)?;
+    processor.set_keep_journal(keep_journal);
+    let mut reorder = args.policy.reorder_window.map(ReorderBuffer::new);
+
+    let mut ingest = read_inputs(&args.input, args.input.rejects.is_some(), |transaction, origin, ingest| {
+        let ready = match reorder.as_mut() {
+            Some(buffer) => buffer.push(transaction, origin),
+            None => vec![(transaction, origin)],
+        };
+        for (transaction, origin) in ready {
+            ingest.process(&mut processor, transaction, origin);
+        }
+    })?;
+    if let Some(buffer) = reorder.as_mut() {
+        for (transaction, origin) in buffer.drain() {
+            ingest.process(&mut processor, transaction, origin);
+        }
+    }
+    processor.finish(args.policy.as_of);
+    let outcome = ingest.write(&args.input)?;
+
+    match args.alerts.as_ref() {
+        Some(path) => {
+            let writer = csv::Writer::from_path(path).map_err(|err| format!("unable to write alerts: {}", err))?;
+            write_alerts(writer, processor.get_alerts()).map_err(|err| format!("unable to write alerts: {}", err))?;
+        }
+        None => {
+            for alert in processor.get_alerts() {
+                eprintln!("alert: client {}, tx {}, {}: {}", alert.client, alert.tx, alert.rule, alert.reason);
+            }
+        }
+    }
+    Ok((processor, clients, outcome))
+}
+
+// what happened to the rows of each input
+struct Ingest {
+    stats: Vec<FileStats>,
+    // only kept when they are written out
+    rejects: Option<Vec<Reject>>,
+    rows: usize,
+}
+
+impl Ingest {
+    fn reject(&mut self, file: usize, line: u64, reason: String) {
+        self.stats[file].rejected += 1;
+        if let Some(rejects) = self.rejects.as_mut() {
+            rejects.push(Reject { file, line, reason });
+        }
+    }
+
+    // rejected transactions are counted against the file they were read from
+    fn process(&mut self, processor: &mut TransactionProcessor, transaction: Transaction, (file, line): (usize, u64)) {
+        if self.rows.is_multiple_of(EVICTION_INTERVAL) {
+            processor.evict_expired_transactions();
+        }
+        self.rows += 1;
+        match processor.process_transaction(transaction) {
+            Ok(()) => self.stats[file].processed += 1,
+            Err(err) => self.reject(file, line, err.to_string()),
+        }
+    }
+
+    fn write(&self, args: &InputArgs) -> Result<Outcome, String> {
+        if let Some(path) = args.stats.as_ref() {
+            let writer = csv::Writer::from_path(path).map_err(|err| format!("unable to write stats: {}", err))?;
+            write_stats(writer, &self.stats).map_err(|err| format!("unable to write stats: {}", err))?;
+        }
+        if let (Some(path), Some(rejects)) = (args.rejects.as_ref(), self.rejects.as_ref()) {
+            let writer = csv::Writer::from_path(path).map_err(|err| format!("unable to write rejects: {}", err))?;
+            write_rejects(writer, &self.stats, rejects).map_err(|err| format!("unable to write rejects: {}", err))?;
+        }
+        if self.stats.iter().any(|file| file.rejected > 0) {
+            Ok(Outcome::Incomplete)
+        } else {
+            Ok(Outcome::Complete)
+        }
+    }
+}
+
+// reads every row of every input, passing the valid ones to `apply` with the file and line they came from
+fn read_inputs<F>(args: &InputArgs, keep_rejects: bool, mut apply: F) -> Result<Ingest, String>
+where
+    F: FnMut(Transaction, (usize, u64), &mut Ingest),
+{
+    let inputs: Vec<&str> = args.inputs.iter().map(|input| input.as_str()).collect();
+    let files = expand_inputs(&inputs, args.order)?;
+    let mut ingest = Ingest {
+        stats: files.iter().cloned().map(FileStats::new).collect(),
+        rejects: if keep_rejects { Some(Vec::new()) } else { None },
+        rows: 0,
+    };
+
+    for (file, path) in files.iter().enumerate() {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(open_input(path)?);
+        // one record for every row, so reading rows allocates nothing
+        let mut record = ByteRecord::new();
+        loop {
+            match reader.read_byte_record(&mut record) {
+                Ok(true) => {}
+                Ok(false) => break,
+                // the file can't be read any further, which no later row makes up for
+                Err(err) if matches!(err.kind(), csv::ErrorKind::Io(_)) => {
+                    return Err(format!("unable to read {}: {}", path.display(), err));
+                }
+                Err(err) => {
+                    ingest.stats[file].rows += 1;
+                    let line = err.position().map_or(0, |position| position.line());
+                    ingest.reject(file, line, err.to_string());
+                    continue;
+                }
+            }
+            ingest.stats[file].rows += 1;
+            let line = record.position().map_or(0, |position| position.line());
+            match Transaction::parse_bytes(&record) {
+                Ok(transaction) if transaction.is_valid() => apply(transaction, (file, line), &mut ingest),
+                Ok(_) => ingest.reject(file, line, "missing or invalid amount".into()),
+                Err(reason) => ingest.reject(file, line, reason.to_string()),
+            }
+        }
+    }
+    Ok(ingest)
+}
+
+fn write_accounts<W: Write>(mut out: W, format: Format, show_credit: bool, processor: TransactionProcessor) -> io::Result<()> {
+    let accounts = processor.get_account_states();
+    match format {
+        Format::Csv => {
+            if show_credit {
+                writeln!(out, "client, available, held, total, locked, credit_limit, headroom, account_type")?;
+            } else {
+                writeln!(out, "client, available, held, total, locked")?;
+            }
+            for account in accounts {
+                if show_credit {
+                    writeln!(out, "{}, {:.4}, {:.4}, {:.4}, {}, {:.4}, {:.4}, {}", account.client, account.available, account.held, account.total, account.locked, account.credit_limit, account.headroom(), account.account_type)?;
+                } else {
+                    writeln!(out, "{}, {:.4}, {:.4}, {:.4}, {}", account.client, account.available, account.held, account.total, account.locked)?;
+                }
+            }
+        }
+        Format::Json => {
+            writeln!(out, "[")?;
+            for (index, account) in accounts.iter().enumerate() {
+                let credit = if show_credit {
+                    format!(
+                        ", \"credit_limit\": {:.4}, \"headroom\": {:.4}, \"account_type\": \"{}\"",
+                        account.credit_limit,
+                        account.headroom(),
+                        account.account_type
+                    )
+                } else {
+                    String::new()
+                };
+                let separator = if index + 1 < accounts.len() { "," } else { "" };
+                writeln!(
+                    out,
+                    "  {{\"client\": {}, \"available\": {:.4}, \"held\": {:.4}, \"total\": {:.4}, \"locked\": {}{}}}{}",
+                    account.client, account.available, account.held, account.total, account.locked, credit, separator
+                )?;
+            }
+            writeln!(out, "]")?;
+        }
+    }
+    out.flush()
+}
+
+// in the format of process, so diff can compare them
+fn write_expected<'a, W: Write>(mut out: W, accounts: impl Iterator<Item = (&'a ClientId, &'a ModelAccount)>) -> io::Result<()> {
+    writeln!(out, "client, available, held, total, locked")?;
+    for (client, account) in accounts {
+        writeln!(out, "{}, {:.4}, {:.4}, {:.4}, {}", client, from_units(account.available), from_units(account.held), from_units(account.total()), account.locked)?;
+    }
+    out.flush()
+}
+
+fn write_differences<W: Write>(mut out: W, differences: &[Difference]) -> io::Result<()> {
+    writeln!(out, "client, field, left, right")?;
+    for difference in differences {
+        writeln!(out, "{}, {}, {}, {}", difference.client, difference.field, difference.left, difference.right)?;
+    }
+    out.flush()
+}
+
+// the row the sides first took differently, after the differences
+fn write_divergence<W: Write>(mut out: W, stats: &[FileStats], divergence: Option<&Divergence>, differ: bool, opened_apart: bool) -> io::Result<()> {
+    let divergence = match divergence {
+        Some(divergence) => divergence,
+        None if differ && opened_apart => {
+            writeln!(out)?;
+            return writeln!(out, "first divergence: none, the sides opened with different balances");
+        }
+        // accounts can still part at the end, when disputes time out or interest is charged
+        None if differ => {
+            writeln!(out)?;
+            return writeln!(out, "first divergence: at the end of the input");
+        }
+        None => return Ok(()),
+    };
+    let (file, line) = divergence.origin;
+    let transaction = divergence.transaction;
+    let taken = |result: &Option<String>| result.clone().unwrap_or_else(|| "held back".into());
+    writeln!(out)?;
+    writeln!(out, "first divergence: {}:{}", stats[file].path.display(), line)?;
+    writeln!(out, "transaction: {}, client {}, tx {}", transaction.transaction_type, transaction.client, transaction.tx)?;
+    writeln!(out, "left: {}", taken(&divergence.left))?;
+    writeln!(out, "right: {}", taken(&divergence.right))?;
+    for difference in divergence.differences.iter() {
+        writeln!(out, "{}: {} {}, {}", difference.client, difference.field, difference.left, difference.right)?;
+    }
+    out.flush()
+}
+
+fn write_discrepancies<W: Write>(mut out: W, discrepancies: &[Discrepancy]) -> io::Result<()> {
+    writeln!(out, "client, issue, field, expected, actual")?;
+    for discrepancy in discrepancies {
+        writeln!(out, "{}, {}, {}, {}, {}", discrepancy.client, discrepancy.issue, discrepancy.field, discrepancy.expected, discrepancy.actual)?;
+    }
+    out.flush()
+}
+
+fn write_stats<W: Write>(mut writer: csv::Writer<W>, stats: &[FileStats]) -> Result<(), csv::Error> {
+    writer.write_record(["file", "rows", "processed", "rejected"])?;
+    for file in stats {
+        writer.write_record([
+            file.path.display().to_string(),
+            file.rows.to_string(),
+            file.processed.to_string(),
+            file.rejected.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_rejects<W: Write>(mut writer: csv::Writer<W>, stats: &[FileStats], rejects: &[Reject]) -> Result<(), csv::Error> {
+    writer.write_record(["file", "line", "reason"])?;
+    for reject in rejects {
+        writer.write_record([
+            stats[reject.file].path.display().to_string(),
+            reject.line.to_string(),
+            reject.reason.clone(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_alerts<W: Write>(mut writer: csv::Writer<W>, alerts: &[Alert]) -> Result<(), csv::Error> {
+    writer.write_record(["client", "tx", "rule", "reason"])?;
+    for alert in alerts {
+        writer.write_record([
+            alert.client.to_string(),
+            alert.tx.to_string(),
+            alert.rule.to_string(),
+            alert.reason.clone(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_statement<W: Write>(mut writer: csv::Writer<W>, journal: &[Event]) -> Result<(), csv::Error> {
+    writer.write_record([
+        "client", "tx", "type", "amount", "timestamp", "synthetic", "available", "held", "total", "locked",
+    ])?;
+    for event in journal {
+        let transaction = event.transaction;
+        writer.write_record([
+            transaction.client.to_string(),
+            transaction.tx.to_string(),
+            transaction.transaction_type.to_string(),
+            transaction.amount.map_or(String::new(), |amount| format!("{:.4}", amount)),
+            transaction.timestamp.map_or(String::new(), |timestamp| timestamp.to_string()),
+            event.synthetic.to_string(),
+            format!("{:.4}", event.account.available),
+            format!("{:.4}", event.account.held),
+            format!("{:.4}", event.account.total),
+            event.account.locked.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map_or(String::new(), |value| format!("{:.4}", value))
+}
+
+fn write_snapshot<W: Write>(mut writer: csv::Writer<W>, clients: &[ClientConfig]) -> Result<(), csv::Error> {
+    writer.write_record([
+        "client", "account_type", "available", "held", "locked", "credit_limit", "interest_rate", "max_amount",
+        "daily_withdrawal",
+    ])?;
+    for client in clients {
+        writer.write_record([
+            client.client.to_string(),
+            client.account_type.to_string(),
+            format!("{:.4}", client.available),
+            format!("{:.4}", client.held),
+            client.locked.to_string(),
+            format!("{:.4}", client.credit_line.limit),
+            client.credit_line.interest_rate.to_string(),
+            optional(client.max_amount),
+            optional(client.daily_withdrawal),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_inputs;
+    use crate::cli::InputArgs;
+    use flate2::{write::GzEncoder, Compression};
+    use std::{fs, io::Write};
+
+    #[test]
+    fn read_inputs_should_fail_on_a_truncated_compressed_input() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        writeln!(encoder, "type, client, tx, amount").unwrap();
+        for tx in 0..1000 {
+            writeln!(encoder, "deposit, {}, {}, 1.0", tx % 7, tx).unwrap();
+        }
+        let bytes = encoder.finish().unwrap();
+        let path = std::env::temp_dir().join(format!("rust_tx_truncated_{}.csv.gz", std::process::id()));
+        fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+
+        let args = InputArgs {
+            inputs: vec![path.to_str().unwrap().to_string()],
+            order: None,
+            stats: None,
+            rejects: None,
+        };
+        let mut rows = 0;
+        let result = read_inputs(&args, true, |_, _, _| rows += 1);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(err) => assert!(err.starts_with("unable to read") && err.contains("rust_tx_truncated")),
+            Ok(_) => panic!("a truncated input was read as complete"),
+        }
+        assert!(rows < 1000);
     }
 }
@@ -0,0 +1,105 @@
+/*
+    Runs every directory under tests/scenarios through the binary. A scenario
+    is an `input.csv` and the `expected.csv` accounts, with optional `args`
+    for extra options and an optional `status` for the expected exit code,
+    0 when there is none. The binary runs in the scenario's directory, so
+    `args` can name other files in it, like `--clients clients.csv`.
+
+    Output is a csv table up to the first blank line, and text after it, like
+    the divergence `diff --replay` prints. Table rows are compared in any
+    order, and numbers by value to four decimal places, so `0.0` matches
+    `0.0000`. The text is compared line by line. Add a case by adding a
+    directory.
+*/
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+fn read(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok()
+}
+
+// a trimmed field, with a number written the same way however many decimals it had
+fn field(field: &str) -> String {
+    let field = field.trim();
+    match field.parse::<f64>() {
+        // adding 0.0 turns -0 into 0
+        Ok(number) => format!("{:.4}", number + 0.0),
+        Err(_) => field.to_string(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Sections {
+    header: Vec<String>,
+    // sorted once their numbers are normalized
+    rows: Vec<Vec<String>>,
+    text: Vec<String>,
+}
+
+fn sections(text: &str) -> Sections {
+    let mut lines = text.lines();
+    let header = lines.next().unwrap_or_default().split(',').map(field).collect();
+    let mut rows: Vec<Vec<String>> = lines
+        .by_ref()
+        .take_while(|line| !line.trim().is_empty())
+        .map(|line| line.split(',').map(field).collect())
+        .collect();
+    rows.sort();
+    let text = lines.map(|line| line.trim_end().to_string()).collect();
+    Sections { header, rows, text }
+}
+
+fn run(scenario: &Path) -> Result<(), String> {
+    let expected = read(&scenario.join("expected.csv")).ok_or("no expected.csv")?;
+    let args = read(&scenario.join("args")).unwrap_or_default();
+    let status: i32 = match read(&scenario.join("status")) {
+        Some(status) => status.trim().parse().map_err(|_| "status is not an exit code")?,
+        None => 0,
+    };
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust_tx"))
+        .current_dir(scenario)
+        .args(args.split_whitespace())
+        .arg("input.csv")
+        .output()
+        .map_err(|err| format!("unable to run: {}", err))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if output.status.code() != Some(status) {
+        return Err(format!(
+            "exited with {:?}, expected {}\n{}",
+            output.status.code(),
+            status,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    if sections(&stdout) != sections(&expected) {
+        return Err(format!("expected\n{}got\n{}", expected, stdout));
+    }
+    Ok(())
+}
+
+#[test]
+fn scenarios_should_produce_the_expected_accounts() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("scenarios");
+    let mut scenarios: Vec<PathBuf> = fs::read_dir(&root)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    scenarios.sort();
+    assert!(!scenarios.is_empty());
+
+    // every scenario runs, so one failure doesn't hide the others
+    let failures: Vec<String> = scenarios
+        .iter()
+        .filter_map(|scenario| {
+            run(scenario)
+                .err()
+                .map(|err| format!("{}: {}", scenario.file_name().unwrap().to_string_lossy(), err))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{} of {} scenarios failed\n\n{}", failures.len(), scenarios.len(), failures.join("\n\n"));
+}